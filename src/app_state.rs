@@ -1,29 +1,520 @@
-use crate::game::city::City;
-use crate::game::player::Player;
+use crate::events::{Action, Event, EventBus};
+use crate::game::army::Army;
+use crate::game::city::{City, CityId};
+use crate::game::combat::Battle;
+use crate::game::command::{execute, Command};
+use crate::game::diplomacy::{Diplomacy, TradeItem, CITY_PRICE, GIFT};
+use crate::game::events::GameEvent;
+use crate::game::messages::MessageLog;
+use crate::game::player::{Player, PlayerId, PlayerType};
+use crate::game::random::GameRng;
+use crate::game::replay::{save_replay, Baseline, Recording};
+use crate::game::save::{quickload, quicksave};
+use crate::game::scenario::{Bounds, Trigger};
+use crate::game::script::{ScriptFlags, Scripts};
+use crate::game::supply::SupplyOverlay;
+use crate::game::turn::TurnManager;
+use crate::game::units::{UnitId, UnitRegistry};
+use crate::game::victory::{GameOver, VictoryConditions};
+use crate::geo::cities::city_at;
+use crate::geo::crs::{Bng, Crs, World};
+use crate::geo::data::{GeoWithPathAndCities, Location};
+use crate::geo::isochrone::{compute_isochrone, Isochrone, DEFAULT_BANDS};
+use crate::geo::measure::Measure;
+use crate::geo::roads::RoadGraph;
+use crate::geo::route::RoutePlanner;
+use crate::geo::territory::Territories;
+use crate::gfx::camera::Camera;
 use crate::gfx::skia::Skia;
-use skia_safe::{scalar, Point};
+use crate::gfx::ui::DiplomacyAction;
+use skia_safe::utils::text_utils::Align;
+use skia_safe::{Color, Paint, PaintStyle, Point};
+use std::collections::HashMap;
 use std::rc::Rc;
 
+const CITY_PICK_PIXELS: f32 = 10.0;
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum Mode {
+    Normal,
+    Measure,
+    Route,
+    Isochrone,
+}
+
 pub struct AppState {
     pub players: Vec<Player>,
-    pub selected_city: Option<Rc<City>>,
+    pub locations: Vec<Rc<Location>>,
+    pub cities: Vec<City>,
+    pub owners: HashMap<CityId, PlayerId>,
+    pub events: Vec<GameEvent>,
+    pub territories: Territories,
+    pub units: UnitRegistry,
+    pub selected_unit: Option<UnitId>,
+    pub combat_log: Vec<Battle>,
+    pub show_combat_log: bool,
+    pub selected_city: Option<CityId>,
+    pub crs: Crs,
+    pub cursor: World,
+    // Where the camera last said it was
+    pub camera: Camera,
+    pub mode: Mode,
+    pub measure: Measure,
+    pub route: RoutePlanner,
+    pub isochrone: Option<Isochrone>,
+    // Minutes
+    pub isochrone_bands: Vec<f64>,
+    pub show_supply: bool,
+    pub supply_overlay: Option<SupplyOverlay>,
+    pub turn: TurnManager,
+    pub diplomacy: Diplomacy,
+    pub show_diplomacy: bool,
+    pub messages: MessageLog,
+    pub rng: GameRng,
+    pub recording: Recording,
+    // Players whose orders arrive over the network
+    pub remote_players: Vec<PlayerId>,
+    // Networked games, where each player's turn began until their orders have been shared
+    pub baselines: Vec<Baseline>,
+    // Hot-seat, the screen is hidden until this player is ready
+    pub handover: Option<PlayerId>,
+    pub victory: VictoryConditions,
+    pub game_over: Option<GameOver>,
+    // Scenarios, the part of the map in play and what's still to happen
+    pub bounds: Option<Bounds>,
+    pub triggers: Vec<Trigger>,
+    pub scripts: Option<Scripts>,
+    pub script_flags: ScriptFlags,
 }
 
 impl AppState {
-    pub fn zoom_to_selected(&self, skia: &mut Skia) {
-        if let Some(selected_city) = self.selected_city.clone() {
-            skia.target = Point::new(selected_city.location.x as scalar, -selected_city.location.y as scalar);
-            skia.zoom = skia.zoom_max / 2.0;
+    pub fn new(mut players: Vec<Player>, geo_and_cities: &GeoWithPathAndCities) -> AppState {
+        let turn = TurnManager::new(&mut players);
+        AppState {
+            players,
+            locations: geo_and_cities.cities.clone(),
+            cities: geo_and_cities.cities.iter().enumerate().map(|(id, location)| City::new(id, location)).collect(),
+            owners: HashMap::new(),
+            events: Vec::new(),
+            territories: Territories::new(&geo_and_cities.cities, &geo_and_cities.land),
+            units: UnitRegistry::default(),
+            selected_unit: None,
+            combat_log: Vec::new(),
+            show_combat_log: false,
+            selected_city: None,
+            crs: Crs::new(),
+            cursor: World::default(),
+            camera: Camera::default(),
+            mode: Mode::Normal,
+            measure: Measure::default(),
+            route: RoutePlanner::default(),
+            isochrone: None,
+            isochrone_bands: DEFAULT_BANDS.to_vec(),
+            show_supply: false,
+            supply_overlay: None,
+            turn,
+            diplomacy: Diplomacy::default(),
+            show_diplomacy: false,
+            messages: MessageLog::default(),
+            rng: GameRng::default(),
+            recording: Recording::default(),
+            remote_players: Vec::new(),
+            baselines: Vec::new(),
+            handover: None,
+            victory: VictoryConditions::default(),
+            game_over: None,
+            bounds: None,
+            triggers: Vec::new(),
+            scripts: None,
+            script_flags: ScriptFlags::new(),
+        }
+    }
+
+    pub fn toggle_mode(&mut self, mode: Mode) {
+        self.measure.clear();
+        self.route.clear();
+        self.isochrone = None;
+        self.mode = if self.mode == mode {
+            Mode::Normal
+        } else {
+            mode
+        };
+    }
+
+    // From the selected city if there is one, otherwise wherever was clicked (snapping to a nearby city)
+    pub fn pick_isochrone_origin(&mut self, geo_and_cities: &GeoWithPathAndCities, point: Option<World>) {
+        let origin = match point {
+            Some(point) => match city_at(&geo_and_cities.cities, point, self.camera.pixels(CITY_PICK_PIXELS)) {
+                Some(city) => Bng::from_scaled(city.x, city.y).to_world(),
+                None => point,
+            },
+            None => match self.selected_city {
+                Some(city) => self.location(city).world(),
+                None => return,
+            },
+        };
+        self.isochrone = compute_isochrone(&geo_and_cities.roads, origin, &self.isochrone_bands);
+    }
+
+    pub fn assign_all_cities(&mut self, owner: PlayerId) {
+        self.players[owner].cities = (0..self.cities.len()).collect();
+        self.owners.extend(self.players[owner].cities.iter().map(|&city| (city, owner)));
+        self.territories.update(&self.players);
+    }
+
+    pub fn is_local_human(&self, player: PlayerId) -> bool {
+        matches!(self.players[player].player_type, PlayerType::Player) && !self.remote_players.contains(&player)
+    }
+
+    // The human at this screen who is giving orders now, if any
+    pub fn giving_orders_here(&self) -> Option<PlayerId> {
+        self.turn.current_player.filter(|&player| self.is_local_human(player) && self.handover.is_none())
+    }
+
+    // Whose view of the map to show: whoever is giving orders here, otherwise the first human at this screen
+    pub fn viewer(&self) -> Option<PlayerId> {
+        self.giving_orders_here().or_else(|| (0..self.players.len()).find(|&player| self.is_local_human(player)))
+    }
+
+    // Hot-seat, hide the map when the turn passes from one human here to another
+    pub fn check_handover(&mut self, last_viewer: Option<PlayerId>) {
+        if self.handover.is_some() {
+            return;
+        }
+        let next = match self.turn.current_player {
+            Some(next) if self.is_local_human(next) => next,
+            _ => return,
+        };
+        let humans = (0..self.players.len()).filter(|&player| self.is_local_human(player)).count();
+        if humans > 1 && last_viewer.is_some_and(|last| last != next) {
+            self.handover = Some(next);
+            self.selected_unit = None;
+            self.selected_city = None;
+        }
+    }
+
+    // Supply as the viewer sees it, worked out again each turn or when cities change hands
+    pub fn update_supply_overlay(&mut self, roads: &RoadGraph) {
+        let viewer = match self.viewer() {
+            Some(viewer) if self.show_supply => viewer,
+            _ => {
+                self.supply_overlay = None;
+                return;
+            }
+        };
+        if self.supply_overlay.as_ref().is_none_or(|overlay| overlay.player != viewer || overlay.turn != self.turn.turn) {
+            self.supply_overlay = Some(SupplyOverlay::new(self, roads, viewer));
+        }
+    }
+
+    pub fn location(&self, city: CityId) -> &Location {
+        &self.locations[city]
+    }
+
+    pub fn city_near(&self, geo_and_cities: &GeoWithPathAndCities, point: World) -> Option<CityId> {
+        city_at(&geo_and_cities.cities, point, self.camera.pixels(CITY_PICK_PIXELS))
+            .and_then(|location| geo_and_cities.cities.iter().position(|l| Rc::ptr_eq(l, &location)))
+    }
+
+    // Pick one of our units, send the selected one to a city, or otherwise select a city
+    pub fn select_or_order(&mut self, geo_and_cities: &GeoWithPathAndCities, point: World, bus: &mut EventBus) {
+        let roads = &geo_and_cities.roads;
+        let radius = self.camera.pixels(CITY_PICK_PIXELS);
+        if let Some(id) = self.giving_orders_here().and_then(|player| self.units.unit_at(player, roads, point, radius)) {
+            bus.push(Event::UnitSelected {
+                unit: Some(id),
+            });
+            return;
+        }
+
+        if let (Some(player), Some(unit)) = (self.giving_orders_here(), self.selected_unit) {
+            if let Some(city) = city_at(&geo_and_cities.cities, point, radius) {
+                if let Some(destination) = roads.nearest_node(Bng::from_scaled(city.x, city.y).to_world()) {
+                    execute(
+                        self,
+                        geo_and_cities,
+                        Command::MoveUnit {
+                            player,
+                            unit,
+                            destination,
+                        },
+                    );
+                }
+                return;
+            }
+        }
+        bus.push(Event::UnitSelected {
+            unit: None,
+        });
+        bus.push(Event::CitySelected {
+            city: self.city_near(geo_and_cities, point),
+        });
+    }
+
+    // Orders for whoever's turn it is, about the selected city or unit
+    fn selected_city_command(&self, make: impl FnOnce(PlayerId, CityId) -> Command) -> Option<Command> {
+        Some(make(self.giving_orders_here()?, self.selected_city?))
+    }
+
+    pub fn raise_army(&mut self, geo_and_cities: &GeoWithPathAndCities, bus: &mut EventBus) {
+        if let Some(command) = self.selected_city_command(|player, city| Command::RaiseArmy {
+            player,
+            city,
+        }) {
+            if execute(self, geo_and_cities, command) {
+                bus.push(Event::UnitSelected {
+                    unit: self.units.newest(),
+                });
+            }
+        }
+    }
+
+    pub fn recruit_spy(&mut self, geo_and_cities: &GeoWithPathAndCities, bus: &mut EventBus) {
+        if let Some(command) = self.selected_city_command(|player, city| Command::RecruitSpy {
+            player,
+            city,
+        }) {
+            if execute(self, geo_and_cities, command) {
+                bus.push(Event::UnitSelected {
+                    unit: self.units.newest(),
+                });
+            }
+        }
+    }
+
+    pub fn sabotage_with_selected(&mut self, geo_and_cities: &GeoWithPathAndCities) {
+        if let (Some(player), Some(unit)) = (self.giving_orders_here(), self.selected_unit) {
+            execute(
+                self,
+                geo_and_cities,
+                Command::Sabotage {
+                    player,
+                    unit,
+                },
+            );
         }
     }
 
-    pub fn zoom_out(&self, skia: &mut Skia) {
-        skia.zoom /= 2.0;
-        skia.zoom = skia.zoom.clamp(skia.zoom_min, skia.zoom_max);
+    pub fn end_turn(&mut self, geo_and_cities: &GeoWithPathAndCities) {
+        if let Some(player) = self.giving_orders_here() {
+            execute(
+                self,
+                geo_and_cities,
+                Command::EndTurn {
+                    player,
+                },
+            );
+        }
+    }
+
+    // Hand the selected city to whoever is giving orders
+    #[cfg(debug_assertions)]
+    pub fn take_selected_city(&mut self, geo_and_cities: &GeoWithPathAndCities) {
+        if let Some(command) = self.selected_city_command(|player, city| Command::TakeCity {
+            player,
+            city,
+        }) {
+            execute(self, geo_and_cities, command);
+        }
+    }
+
+    // Something picked on the diplomacy screen, by whoever is giving orders
+    pub fn diplomacy_action(&mut self, geo_and_cities: &GeoWithPathAndCities, action: DiplomacyAction) {
+        let player = match self.giving_orders_here() {
+            Some(player) => player,
+            None => return,
+        };
+        let command = match action {
+            DiplomacyAction::DeclareWar(target) => Command::DeclareWar {
+                player,
+                target,
+            },
+            DiplomacyAction::ProposePeace(target) => Command::ProposePeace {
+                player,
+                target,
+            },
+            DiplomacyAction::ProposeAlliance(target) => Command::ProposeAlliance {
+                player,
+                target,
+            },
+            DiplomacyAction::SellCity(target) => {
+                let city = match self.selected_city.filter(|&city| self.owner_of(city) == Some(player)) {
+                    Some(city) => city,
+                    None => {
+                        self.messages.add(self.turn.turn, String::from("Select one of your cities to sell"));
+                        return;
+                    }
+                };
+                let price = (self.cities[city].economy.population / 1000.0 * CITY_PRICE).round();
+                Command::OfferTrade {
+                    player,
+                    target,
+                    give: vec![TradeItem::City(city)],
+                    take: vec![TradeItem::Gold(price)],
+                }
+            }
+            DiplomacyAction::SendGold(target) => Command::OfferTrade {
+                player,
+                target,
+                give: vec![TradeItem::Gold(GIFT)],
+                take: Vec::new(),
+            },
+            DiplomacyAction::Accept(proposal) => Command::AcceptProposal {
+                player,
+                proposal,
+            },
+            DiplomacyAction::Reject(proposal) => Command::RejectProposal {
+                player,
+                proposal,
+            },
+        };
+        execute(self, geo_and_cities, command);
+    }
+
+    pub fn owner_of(&self, city: CityId) -> Option<PlayerId> {
+        self.owners.get(&city).copied()
     }
 
-    pub fn zoom_in(&self, skia: &mut Skia) {
-        skia.zoom *= 2.0;
-        skia.zoom = skia.zoom.clamp(skia.zoom_min, skia.zoom_max);
+    // Moves the city from its current owner to another, returns false if there was nothing to move
+    pub fn transfer_city(&mut self, city: CityId, to: PlayerId) -> bool {
+        let from = match self.owner_of(city) {
+            Some(from) if from != to => from,
+            _ => return false,
+        };
+        if !self.players[from].remove_city(city) {
+            return false;
+        }
+        self.players[to].add_city(city);
+        self.owners.insert(city, to);
+        self.events.push(GameEvent::OwnershipChanged {
+            city,
+            from,
+            to,
+        });
+        true
+    }
+
+    // Input and the UI's requests, then anything that follows on from them is raised on the bus
+    pub fn handle_event(&mut self, event: &Event, geo_and_cities: &GeoWithPathAndCities, bus: &mut EventBus) {
+        match *event {
+            Event::Action(action) => self.handle_action(action, geo_and_cities, bus),
+            Event::CursorMoved {
+                world,
+            } => self.cursor = world,
+            Event::CameraMoved {
+                camera,
+            } => self.camera = camera,
+            Event::MapClicked {
+                world,
+            } => match self.mode {
+                Mode::Measure => self.measure.add_vertex(world, &geo_and_cities.ways, &self.camera),
+                Mode::Route => self.route.pick(&geo_and_cities.roads, world),
+                Mode::Isochrone => self.pick_isochrone_origin(geo_and_cities, Some(world)),
+                Mode::Normal => self.select_or_order(geo_and_cities, world, bus),
+            },
+            Event::Diplomacy(action) => self.diplomacy_action(geo_and_cities, action),
+            Event::CitySelected {
+                city,
+            } => self.selected_city = city,
+            Event::UnitSelected {
+                unit,
+            } => self.selected_unit = unit,
+            Event::Game(ref event) => self.on_game_event(event),
+            _ => {}
+        }
+    }
+
+    fn handle_action(&mut self, action: Action, geo_and_cities: &GeoWithPathAndCities, bus: &mut EventBus) {
+        match action {
+            Action::ZoomToSelected => {
+                if let Some(city) = self.selected_city {
+                    bus.push(Event::CameraFocused {
+                        target: self.location(city).world(),
+                    });
+                }
+            }
+            Action::ToggleMode(mode) => {
+                self.toggle_mode(mode);
+                if self.mode == Mode::Isochrone {
+                    self.pick_isochrone_origin(geo_and_cities, None);
+                }
+            }
+            Action::EndTurn if self.handover.is_some() => self.handover = None,
+            Action::EndTurn => self.end_turn(geo_and_cities),
+            #[cfg(debug_assertions)]
+            Action::TakeCity => self.take_selected_city(geo_and_cities),
+            Action::RaiseArmy => self.raise_army(geo_and_cities, bus),
+            Action::RecruitSpy => self.recruit_spy(geo_and_cities, bus),
+            Action::Sabotage => self.sabotage_with_selected(geo_and_cities),
+            Action::ToggleCombatLog => self.show_combat_log = !self.show_combat_log,
+            Action::ToggleDiplomacy => self.show_diplomacy = !self.show_diplomacy,
+            Action::ToggleSupply => self.show_supply = !self.show_supply,
+            Action::ToggleMeasureClosed if self.mode == Mode::Measure => self.measure.closed = !self.measure.closed,
+            Action::ToggleMeasureSnap if self.mode == Mode::Measure => self.measure.snap = !self.measure.snap,
+            Action::Undo if self.mode == Mode::Measure => self.measure.undo(),
+            Action::Clear if self.mode == Mode::Measure => self.measure.clear(),
+            Action::Clear if self.mode == Mode::Route => self.route.clear(),
+            Action::DismissHandover => self.handover = None,
+            Action::Quicksave => quicksave(self),
+            Action::SaveReplay => save_replay(self),
+            // Not in a network game, the others would be left behind
+            Action::Quickload if self.remote_players.is_empty() => {
+                if let Some(camera) = quickload(self, geo_and_cities) {
+                    bus.push(Event::CameraSet {
+                        target: World::new(camera.x, camera.y),
+                        zoom: camera.zoom,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Cities changing hands and units moving, whether or not anyone is watching
+    pub fn on_game_event(&mut self, event: &GameEvent) {
+        match *event {
+            GameEvent::OwnershipChanged {
+                city,
+                from,
+                to,
+            } => {
+                let message = format!("{} passed from {} to {}", self.location(city).name, self.players[from].name, self.players[to].name);
+                self.messages.add(self.turn.turn, message);
+                self.territories.update(&self.players);
+                self.supply_overlay = None;
+            }
+            // Armies cut supply lines, so the overlay is out of date once one moves
+            GameEvent::UnitMoved {
+                unit,
+            } => {
+                if self.units.get_as::<Army>(unit).is_some() {
+                    self.supply_overlay = None;
+                }
+            }
+        }
+    }
+
+    // Without a bus, as in headless games and replays, events are handled straight away
+    pub fn process_events(&mut self) {
+        for event in std::mem::take(&mut self.events) {
+            self.on_game_event(&event);
+        }
+    }
+
+    pub fn show_cursor_position(&self, skia: &mut Skia) {
+        let bng = self.cursor.to_bng();
+        let lat_lon = match self.crs.bng_to_lat_lon(bng) {
+            Some(lat_lon) => lat_lon.to_dms(),
+            None => String::from("-"),
+        };
+        let grid_ref = bng.to_grid_ref(6).unwrap_or_else(|| String::from("Off grid"));
+        let text = format!("{}  {}", lat_lon, grid_ref);
+
+        let mut paint = Paint::default();
+        paint.set_style(PaintStyle::StrokeAndFill);
+        paint.set_color(Color::BLACK);
+        let font = skia.font_main.clone();
+        let canvas = skia.get_canvas();
+        canvas.draw_text_align(text, Point::new(10.0, 50.0), &font, &paint, Align::Left);
     }
 }
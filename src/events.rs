@@ -0,0 +1,108 @@
+use crate::app_state::Mode;
+use crate::game::city::CityId;
+use crate::game::events::GameEvent;
+use crate::game::units::UnitId;
+use crate::geo::crs::World;
+use crate::gfx::camera::Camera;
+use crate::gfx::ui::DiplomacyAction;
+use std::collections::VecDeque;
+
+// Something the player asked for by key, whatever it does
+#[derive(Clone, Copy, PartialEq)]
+pub enum Action {
+    ZoomToSelected,
+    ToggleMode(Mode),
+    EndTurn,
+    #[cfg(debug_assertions)]
+    TakeCity,
+    RaiseArmy,
+    RecruitSpy,
+    Sabotage,
+    ToggleCombatLog,
+    ToggleDiplomacy,
+    ToggleSupply,
+    ToggleMeasureClosed,
+    ToggleMeasureSnap,
+    Undo,
+    Clear,
+    DismissHandover,
+    Quicksave,
+    Quickload,
+    SaveReplay,
+}
+
+/// Everything that happens in a frame. Input, the camera, the UI and the game each pick
+/// out what concerns them and may raise further events, rather than reaching into one another.
+pub enum Event {
+    Quit,
+    Action(Action),
+
+    // Raw input, already in map coordinates where it matters
+    CursorMoved {
+        world: World,
+    },
+    Clicked {
+        x: i32,
+        y: i32,
+        world: World,
+    },
+
+    // Camera, pans are in screen pixels
+    CameraPanned {
+        dx: f32,
+        dy: f32,
+    },
+    CameraZoomed {
+        delta: f32,
+    },
+    CameraScaled {
+        factor: f32,
+    },
+    CameraFocused {
+        target: World,
+    },
+    CameraSet {
+        target: World,
+        zoom: f32,
+    },
+    // Raised by the camera whenever it changes
+    CameraMoved {
+        camera: Camera,
+    },
+
+    // Clicks that weren't on the UI
+    MapClicked {
+        world: World,
+    },
+    Diplomacy(DiplomacyAction),
+
+    CitySelected {
+        city: Option<CityId>,
+    },
+    UnitSelected {
+        unit: Option<UnitId>,
+    },
+
+    // From the game itself, cities changing hands and units moving
+    Game(GameEvent),
+}
+
+#[derive(Default)]
+pub struct EventBus {
+    queue: VecDeque<Event>,
+}
+
+impl EventBus {
+    pub fn push(&mut self, event: Event) {
+        self.queue.push_back(event);
+    }
+
+    pub fn extend(&mut self, events: impl IntoIterator<Item = Event>) {
+        self.queue.extend(events);
+    }
+
+    // First in, first out, so events raised while handling another come after it
+    pub fn pop(&mut self) -> Option<Event> {
+        self.queue.pop_front()
+    }
+}
@@ -0,0 +1,311 @@
+use crate::app_state::AppState;
+use crate::game::army::{Army, ARMY_COST};
+use crate::game::city::CityId;
+use crate::game::combat::{city_defence_bonus, garrison};
+use crate::game::command::{execute, Command};
+use crate::game::diplomacy::{Offer, Proposal, Relation, TradeItem, CITY_PRICE};
+use crate::game::fog::Vision;
+use crate::game::player::{PlayerId, PlayerType};
+use crate::game::units::{UnitId, UnitTrait};
+use crate::geo::crs::Bng;
+use crate::geo::data::GeoWithPathAndCities;
+use std::collections::BTreeSet;
+
+// Enemy armies this close (metres) to one of our cities are a threat to it
+const THREAT_DISTANCE: f64 = 30_000.0;
+
+// Furthest (metres) an army will be sent to take a city
+const EXPAND_DISTANCE: f64 = 150_000.0;
+
+// Only attack cities we expect to beat by this margin
+const ATTACK_MARGIN: f64 = 1.2;
+
+// Armies kept for every city held, plus one
+const ARMIES_PER_CITY: f64 = 0.5;
+
+// Trades are only taken if they come out this far ahead
+const TRADE_MARGIN: f64 = 1.1;
+
+/// Decides a computer player's orders. Given the whole game read only, it returns the
+/// commands to carry out, which go through the same checks as a human's.
+pub trait Strategy {
+    fn name(&self) -> &str;
+    fn give_orders(&self, player: PlayerId, app_state: &AppState, geo_and_cities: &GeoWithPathAndCities) -> Vec<Command>;
+
+    // Whether to accept a diplomatic proposal made to this player
+    fn respond(&self, _player: PlayerId, _proposal: &Proposal, _app_state: &AppState) -> bool {
+        false
+    }
+}
+
+// Total strength of a player's armies
+fn army_strength(app_state: &AppState, player: PlayerId) -> u32 {
+    app_state.units.of_type::<Army>().filter(|army| army.owner == player).map(|army| army.strength).sum()
+}
+
+// Gold equivalent of what's on offer, a capital isn't for sale
+fn trade_value(app_state: &AppState, owner: PlayerId, items: &[TradeItem]) -> f64 {
+    items
+        .iter()
+        .map(|item| match item {
+            TradeItem::Gold(gold) => *gold,
+            TradeItem::Production(production) => *production,
+            TradeItem::City(city) if app_state.players[owner].capital == Some(*city) => f64::INFINITY,
+            TradeItem::City(city) => app_state.cities[*city].economy.population / 1000.0 * CITY_PRICE,
+        })
+        .sum()
+}
+
+// Strategies by name, for picking one in setup or loading a saved game
+pub fn strategy_named(name: &str) -> Option<Box<dyn Strategy>> {
+    match name {
+        "Heuristic" => Some(Box::new(Heuristic)),
+        _ => None,
+    }
+}
+
+// Defends its cities, then sends its other armies to take the weakest cities nearby and raises more when it can
+#[derive(Default)]
+pub struct Heuristic;
+
+impl Strategy for Heuristic {
+    fn name(&self) -> &str {
+        "Heuristic"
+    }
+
+    fn give_orders(&self, player: PlayerId, app_state: &AppState, geo_and_cities: &GeoWithPathAndCities) -> Vec<Command> {
+        let roads = &geo_and_cities.roads;
+        let city_at = |city: CityId| Bng::from_scaled(geo_and_cities.cities[city].x, geo_and_cities.cities[city].y);
+        let node_at = |city: CityId| roads.nearest_node(city_at(city).to_world());
+        let vision = Vision::new(app_state, player, roads);
+        let mut orders = Vec::new();
+
+        let armies: Vec<&Army> = app_state.units.of_type::<Army>().filter(|army| army.owner == player).collect();
+        let mut idle: Vec<(UnitId, Bng, u32)> =
+            armies.iter().filter(|army| !army.movement.is_moving()).map(|army| (army.id, roads.nodes[army.movement.node].bng(), army.strength)).collect();
+        let own_cities: Vec<CityId> = app_state.players[player].cities.clone();
+
+        // Defend: the nearest idle army goes back to any city with enemies in sight nearby
+        let enemies: Vec<Bng> = app_state
+            .units
+            .of_type::<Army>()
+            .filter(|army| app_state.diplomacy.is_hostile(player, army.owner) && vision.can_see(army.at(roads)))
+            .map(|army| army.at(roads).to_bng())
+            .collect();
+        let threatened: Vec<CityId> =
+            own_cities.iter().copied().filter(|&city| enemies.iter().any(|enemy| enemy.distance(city_at(city)) <= THREAT_DISTANCE)).collect();
+        for &city in &threatened {
+            let nearest = idle.iter().enumerate().min_by(|a, b| a.1 .1.distance(city_at(city)).total_cmp(&b.1 .1.distance(city_at(city))));
+            if let Some((index, &(unit, _, _))) = nearest {
+                idle.remove(index);
+                if let Some(destination) = node_at(city) {
+                    if destination != app_state.units.get(unit).map_or(destination, |unit| unit.movement().node) {
+                        orders.push(Command::MoveUnit {
+                            player,
+                            unit,
+                            destination,
+                        });
+                    }
+                }
+            }
+        }
+
+        // Outmatched by someone whose armies are at our cities, ask them for peace
+        let attackers: BTreeSet<PlayerId> = app_state
+            .units
+            .of_type::<Army>()
+            .filter(|army| app_state.diplomacy.is_hostile(player, army.owner) && app_state.players[army.owner].takes_part() && vision.can_see(army.at(roads)))
+            .filter(|army| threatened.iter().any(|&city| army.at(roads).to_bng().distance(city_at(city)) <= THREAT_DISTANCE))
+            .map(|army| army.owner)
+            .collect();
+        let ours = army_strength(app_state, player);
+        for target in attackers {
+            let asked = app_state.diplomacy.proposals.iter().any(|proposal| proposal.from == player && proposal.to == target);
+            if !asked && army_strength(app_state, target) > ours {
+                orders.push(Command::ProposePeace {
+                    player,
+                    target,
+                });
+            }
+        }
+
+        // Expand: each remaining army picks the best city it should win, no two armies the same one
+        let mut targets = BTreeSet::new();
+        for (unit, at, strength) in idle {
+            let best = geo_and_cities
+                .cities
+                .iter()
+                .enumerate()
+                .filter(|(city, _)| !targets.contains(city) && app_state.owner_of(*city).is_some_and(|owner| app_state.diplomacy.is_hostile(player, owner)))
+                .filter_map(|(city, _)| {
+                    let distance = city_at(city).distance(at);
+                    let population = app_state.cities[city].economy.population;
+                    let defence = garrison(population) as f64 * city_defence_bonus(population) as f64;
+                    (distance <= EXPAND_DISTANCE && defence * ATTACK_MARGIN < strength as f64).then_some((city, population / (distance + 10_000.0)))
+                })
+                .max_by(|a, b| a.1.total_cmp(&b.1));
+            if let Some((city, _)) = best {
+                targets.insert(city);
+                if let Some(destination) = node_at(city) {
+                    orders.push(Command::MoveUnit {
+                        player,
+                        unit,
+                        destination,
+                    });
+                }
+            }
+        }
+
+        // Recruit: at a threatened city first, otherwise the biggest
+        let wanted = (own_cities.len() as f64 * ARMIES_PER_CITY) as usize + 1;
+        let mut production = app_state.players[player].production;
+        let mut count = armies.len();
+        let mut bases = threatened.clone();
+        bases.extend(own_cities.iter().copied().max_by(|&a, &b| app_state.cities[a].economy.population.total_cmp(&app_state.cities[b].economy.population)));
+        while production >= ARMY_COST && count < wanted {
+            if let Some(&city) = bases.first() {
+                orders.push(Command::RaiseArmy {
+                    player,
+                    city,
+                });
+                if bases.len() > 1 {
+                    bases.remove(0);
+                }
+            }
+            production -= ARMY_COST;
+            count += 1;
+        }
+
+        orders
+    }
+
+    // Peace with anyone stronger, alliances with those already at peace, and trades that come out ahead
+    fn respond(&self, player: PlayerId, proposal: &Proposal, app_state: &AppState) -> bool {
+        match &proposal.offer {
+            Offer::Peace => army_strength(app_state, proposal.from) >= army_strength(app_state, player),
+            Offer::Alliance => app_state.diplomacy.relation(player, proposal.from) == Relation::Peace,
+            Offer::Trade {
+                give,
+                take,
+            } => trade_value(app_state, proposal.from, give) > trade_value(app_state, player, take) * TRADE_MARGIN,
+        }
+    }
+}
+
+// If a computer player is giving orders, carry them out and end its turn. Returns true if it did.
+pub fn take_ai_turn(app_state: &mut AppState, geo_and_cities: &GeoWithPathAndCities) -> bool {
+    let player = match app_state.turn.current_player {
+        Some(player) if !app_state.remote_players.contains(&player) => player,
+        _ => return false,
+    };
+    // Answer any proposals first, so the orders are given knowing where things stand
+    let answers: Vec<Command> = match &app_state.players[player].player_type {
+        PlayerType::Ai(strategy) => app_state
+            .diplomacy
+            .proposals_to(player)
+            .map(|proposal| {
+                if strategy.respond(player, proposal, app_state) {
+                    Command::AcceptProposal {
+                        player,
+                        proposal: proposal.id,
+                    }
+                } else {
+                    Command::RejectProposal {
+                        player,
+                        proposal: proposal.id,
+                    }
+                }
+            })
+            .collect(),
+        _ => return false,
+    };
+    for command in answers {
+        execute(app_state, geo_and_cities, command);
+    }
+    let orders = match &app_state.players[player].player_type {
+        PlayerType::Ai(strategy) => strategy.give_orders(player, app_state, geo_and_cities),
+        _ => return false,
+    };
+    for command in orders {
+        execute(app_state, geo_and_cities, command);
+    }
+    execute(
+        app_state,
+        geo_and_cities,
+        Command::EndTurn {
+            player,
+        },
+    );
+    true
+}
+
+/// Play a game between computer players without a window, for simulation and testing.
+/// Stops early if a human is due to give orders.
+pub fn run_headless(app_state: &mut AppState, geo_and_cities: &GeoWithPathAndCities, turns: u32) {
+    while app_state.turn.turn <= turns {
+        let turn = app_state.turn.turn;
+        if let Some(game_over) = &app_state.game_over {
+            println!("{}", game_over.summary(app_state));
+            break;
+        }
+        if !take_ai_turn(app_state, geo_and_cities) {
+            println!("Turn {}: no computer player to give orders, stopping", turn);
+            break;
+        }
+        app_state.process_events();
+        if app_state.turn.turn != turn {
+            summary(app_state);
+        }
+    }
+}
+
+fn summary(app_state: &AppState) {
+    for (id, player) in app_state.players.iter().enumerate().filter(|(_, player)| player.takes_turns()) {
+        let armies = app_state.units.of_type::<Army>().filter(|army| army.owner == id).count();
+        let strategy = match &player.player_type {
+            PlayerType::Ai(strategy) => strategy.name(),
+            _ => "Human",
+        };
+        println!(
+            "  {} ({}): {} cities, {} armies, treasury {:.0}, production {:.0}",
+            player.name,
+            strategy,
+            player.cities.len(),
+            armies,
+            player.treasury,
+            player.production
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::setup::{new_game, GameSetup};
+    use crate::geo::data::test_geo;
+
+    const TURNS: u32 = 5;
+
+    // State hashes at the start of each turn of a game between computer players
+    fn play(seed: u64) -> Vec<(u32, u64)> {
+        let geo_and_cities = test_geo();
+        let mut setup = GameSetup::default();
+        setup.add_player();
+        setup.players.iter_mut().for_each(|player| player.human = false);
+        setup.seed = Some(seed);
+        let mut app_state = new_game(&setup, &geo_and_cities).unwrap();
+        while app_state.turn.turn <= TURNS && app_state.game_over.is_none() {
+            assert!(take_ai_turn(&mut app_state, &geo_and_cities));
+            app_state.process_events();
+        }
+        app_state.recording.hashes
+    }
+
+    #[test]
+    fn ai_games_advance_the_same_way_each_time() {
+        let hashes = play(7);
+        assert!(hashes.len() > 1, "the game never got past the first turn");
+        assert!(hashes.windows(2).all(|pair| pair[1].0 == pair[0].0 + 1));
+        assert_eq!(hashes, play(7));
+    }
+}
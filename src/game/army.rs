@@ -1,11 +1,82 @@
-use crate::game::units::UnitTrait;
+use crate::game::movement::Movement;
+use crate::game::player::PlayerId;
+use crate::game::save::SavedUnit;
+use crate::game::units::{as_any, Marker, UnitId, UnitTrait};
+use crate::geo::roads::NodeId;
+use serde::{Deserialize, Serialize};
 
+// Seconds of driving per turn, so a turn covers ~110km of motorway or ~35km of lanes
+pub const MOVEMENT_POINTS: f64 = 60.0 * 60.0;
+pub const ARMY_COST: f64 = 20.0;
+pub const ARMY_STRENGTH: u32 = 100;
+
+// Gold per turn for every hundred men
+const UPKEEP_PER_HUNDRED: f64 = 2.0;
+
+// How far an army can see, km
+const ARMY_VISION: f32 = 10.0;
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Army {
+    pub id: UnitId,
     pub name: String,
+    pub owner: PlayerId,
+    pub strength: u32,
+    pub movement: Movement,
 }
 
 impl UnitTrait for Army {
     fn get_name(&self) -> &str {
         self.name.as_str()
     }
+
+    fn id(&self) -> UnitId {
+        self.id
+    }
+
+    fn owner(&self) -> PlayerId {
+        self.owner
+    }
+
+    fn movement(&self) -> &Movement {
+        &self.movement
+    }
+
+    fn movement_mut(&mut self) -> &mut Movement {
+        &mut self.movement
+    }
+
+    fn upkeep(&self) -> f64 {
+        self.strength as f64 / 100.0 * UPKEEP_PER_HUNDRED
+    }
+
+    fn vision_radius(&self) -> f32 {
+        ARMY_VISION
+    }
+
+    fn marker(&self) -> Marker {
+        Marker::Circle
+    }
+
+    fn label(&self) -> Option<String> {
+        Some(self.strength.to_string())
+    }
+
+    fn saved(&self) -> SavedUnit {
+        SavedUnit::Army(self.clone())
+    }
+
+    as_any!();
+}
+
+impl Army {
+    pub fn new(id: UnitId, name: String, owner: PlayerId, strength: u32, node: NodeId) -> Army {
+        Army {
+            id,
+            name,
+            owner,
+            strength,
+            movement: Movement::new(node, MOVEMENT_POINTS),
+        }
+    }
 }
@@ -1,7 +1,22 @@
+use crate::game::economy::Economy;
 use crate::geo::data::Location;
-use std::rc::Rc;
+use serde::{Deserialize, Serialize};
 
-#[derive(PartialEq)]
+// Index into the loaded city locations
+pub type CityId = usize;
+
+// A city's state in the game, the location it stands for is looked up by id
+#[derive(Clone, Serialize, Deserialize)]
 pub struct City {
-    pub location: Rc<Location>,
+    pub id: CityId,
+    pub economy: Economy,
+}
+
+impl City {
+    pub fn new(id: CityId, location: &Location) -> City {
+        City {
+            id,
+            economy: Economy::new(location.population),
+        }
+    }
 }
@@ -0,0 +1,225 @@
+use crate::app_state::AppState;
+use crate::game::army::Army;
+use crate::game::city::CityId;
+use crate::game::player::PlayerId;
+use crate::game::units::UnitId;
+use crate::geo::crs::{Bng, World};
+use crate::geo::data::GeoWithPathAndCities;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+// Armies closer than this (metres) fight
+const ENGAGE_DISTANCE: f64 = 2000.0;
+
+// An army this close (metres) to an enemy city assaults it
+const ASSAULT_DISTANCE: f64 = 3000.0;
+
+// Defenders on the roughest ground fight at up to this much extra
+const TERRAIN_BONUS: f32 = 0.5;
+
+// Garrison raised from a city's own population
+const GARRISON_PER_THOUSAND: f64 = 2.0;
+
+// People lost with each soldier of the garrison
+const CIVILIANS_PER_SOLDIER: f64 = 50.0;
+
+// Biggest cities defend at up to double strength
+const CITY_BONUS_POPULATION: f64 = 250_000.0;
+
+// Luck either way
+const ROLL: f32 = 0.2;
+
+enum Side {
+    Army(UnitId),
+    Garrison(CityId),
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Battle {
+    pub turn: u32,
+    pub place: String,
+    pub attacker: PlayerId,
+    pub defender: PlayerId,
+    pub attacker_strength: u32,
+    pub defender_strength: u32,
+    pub defence_bonus: f32,
+    pub attacker_losses: u32,
+    pub defender_losses: u32,
+    pub attacker_won: bool,
+}
+
+impl Battle {
+    pub fn summary(&self, app_state: &AppState) -> String {
+        let name = |player: PlayerId| app_state.players[player].name.clone();
+        format!(
+            "{}: {} ({}, lost {}) vs {} ({} x{:.2}, lost {}), {} won",
+            self.place,
+            name(self.attacker),
+            self.attacker_strength,
+            self.attacker_losses,
+            name(self.defender),
+            self.defender_strength,
+            self.defence_bonus,
+            self.defender_losses,
+            name(if self.attacker_won {
+                self.attacker
+            } else {
+                self.defender
+            }),
+        )
+    }
+}
+
+// Returns (attacker won, attacker losses, defender losses)
+fn fight(rng: &mut impl Rng, attacker: u32, defender: u32, defence_bonus: f32) -> (bool, u32, u32) {
+    let attack = attacker as f32 * rng.random_range(1.0 - ROLL..=1.0 + ROLL);
+    let defence = defender as f32 * defence_bonus * rng.random_range(1.0 - ROLL..=1.0 + ROLL);
+    if attack <= 0.0 || defence <= 0.0 {
+        return (attack > defence, 0, defender);
+    }
+
+    // The loser takes the worse of it, more so the more lopsided the fight
+    let ratio = attack.max(defence) / attack.min(defence);
+    let loser = (0.3 * ratio).clamp(0.2, 1.0);
+    let winner = (0.3 / ratio).clamp(0.05, 0.5);
+    let attacker_won = attack > defence;
+    let (attacker_loss, defender_loss) = if attacker_won {
+        (winner, loser)
+    } else {
+        (loser, winner)
+    };
+    (attacker_won, (attacker as f32 * attacker_loss).ceil() as u32, (defender as f32 * defender_loss).ceil() as u32)
+}
+
+// Soldiers a city of this size defends itself with
+pub fn garrison(population: f64) -> u32 {
+    (population / 1000.0 * GARRISON_PER_THOUSAND).round() as u32
+}
+
+pub fn city_defence_bonus(population: f64) -> f32 {
+    1.0 + (population / CITY_BONUS_POPULATION).min(1.0) as f32
+}
+
+fn terrain_bonus(geo_and_cities: &GeoWithPathAndCities, point: World) -> f32 {
+    1.0 + geo_and_cities.dem.ruggedness(point) * TERRAIN_BONUS
+}
+
+/// Fight every battle the last moves brought about: armies that have met, then assaults on cities.
+/// Whoever moved in is the attacker, the army standing its ground gets the terrain bonus.
+pub fn resolve_combat(app_state: &mut AppState, geo_and_cities: &GeoWithPathAndCities) {
+    let roads = &geo_and_cities.roads;
+    let position = |army: &Army| roads.nodes[army.movement.node].bng();
+
+    // Armies that have met
+    let ids = app_state.units.ids_of_type::<Army>();
+    for (i, &a) in ids.iter().enumerate() {
+        for &b in &ids[i + 1..] {
+            let (army_a, army_b) = match (app_state.units.get_as::<Army>(a), app_state.units.get_as::<Army>(b)) {
+                (Some(army_a), Some(army_b)) => (army_a, army_b),
+                _ => continue,
+            };
+            if army_a.strength == 0 || army_b.strength == 0 || !app_state.diplomacy.is_hostile(army_a.owner, army_b.owner) {
+                continue;
+            }
+            let (at_a, at_b) = (position(army_a), position(army_b));
+            if at_a.distance(at_b) > ENGAGE_DISTANCE {
+                continue;
+            }
+            let (attacker, defender, at) = if army_b.movement.moved && !army_a.movement.moved {
+                (b, a, at_a)
+            } else {
+                (a, b, at_b)
+            };
+            let place = format!("Near {}", nearest_city_name(geo_and_cities, at));
+            let bonus = terrain_bonus(geo_and_cities, at.to_world());
+            battle(app_state, place, Side::Army(attacker), Side::Army(defender), bonus);
+        }
+    }
+
+    // Assaults on enemy cities
+    for id in ids {
+        let (owner, at) = match app_state.units.get_as::<Army>(id) {
+            Some(army) if army.strength > 0 => (army.owner, position(army)),
+            _ => continue,
+        };
+        let target = geo_and_cities.cities.iter().enumerate().find(|(city, location)| {
+            let hostile = app_state.owner_of(*city).is_some_and(|city_owner| app_state.diplomacy.is_hostile(owner, city_owner));
+            hostile && Bng::from_scaled(location.x, location.y).distance(at) <= ASSAULT_DISTANCE
+        });
+        if let Some((city, location)) = target {
+            let bonus = city_defence_bonus(location.population as f64) * terrain_bonus(geo_and_cities, at.to_world());
+            battle(app_state, location.name.clone(), Side::Army(id), Side::Garrison(city), bonus);
+        }
+    }
+
+    // Clear away the destroyed
+    app_state.units.retain(|unit| unit.as_any().downcast_ref::<Army>().is_none_or(|army| army.strength > 0));
+    if app_state.selected_unit.is_some_and(|id| !app_state.units.contains(id)) {
+        app_state.selected_unit = None;
+    }
+}
+
+fn battle(app_state: &mut AppState, place: String, attacker: Side, defender: Side, defence_bonus: f32) {
+    let (attacker_owner, attacker_strength) = side(app_state, &attacker);
+    let (defender_owner, defender_strength) = side(app_state, &defender);
+    let (attacker_won, attacker_losses, defender_losses) = fight(&mut app_state.rng, attacker_strength, defender_strength, defence_bonus);
+
+    if let Side::Army(id) = attacker {
+        let army = app_state.units.get_as_mut::<Army>(id).unwrap();
+        army.strength = army.strength.saturating_sub(attacker_losses);
+        if !attacker_won {
+            army.movement.route.clear();
+        }
+    }
+    match defender {
+        Side::Army(id) => {
+            let army = app_state.units.get_as_mut::<Army>(id).unwrap();
+            army.strength = army.strength.saturating_sub(defender_losses);
+        }
+        Side::Garrison(city) => {
+            let economy = &mut app_state.cities[city].economy;
+            economy.population = (economy.population - defender_losses as f64 * CIVILIANS_PER_SOLDIER).max(0.0);
+            economy.update();
+            if attacker_won {
+                app_state.transfer_city(city, attacker_owner);
+            }
+        }
+    }
+
+    let result = Battle {
+        turn: app_state.turn.turn,
+        place,
+        attacker: attacker_owner,
+        defender: defender_owner,
+        attacker_strength,
+        defender_strength,
+        defence_bonus,
+        attacker_losses: attacker_losses.min(attacker_strength),
+        defender_losses: defender_losses.min(defender_strength),
+        attacker_won,
+    };
+    app_state.messages.add(result.turn, result.summary(app_state));
+    app_state.combat_log.push(result);
+}
+
+fn side(app_state: &AppState, side: &Side) -> (PlayerId, u32) {
+    match side {
+        Side::Army(id) => {
+            let army = app_state.units.get_as::<Army>(*id).unwrap();
+            (army.owner, army.strength)
+        }
+        Side::Garrison(city) => {
+            let population = app_state.cities[*city].economy.population;
+            (app_state.owner_of(*city).unwrap_or_default(), garrison(population))
+        }
+    }
+}
+
+fn nearest_city_name(geo_and_cities: &GeoWithPathAndCities, at: Bng) -> String {
+    geo_and_cities
+        .cities
+        .iter()
+        .min_by(|a, b| Bng::from_scaled(a.x, a.y).distance(at).total_cmp(&Bng::from_scaled(b.x, b.y).distance(at)))
+        .map(|city| city.name.clone())
+        .unwrap_or_default()
+}
@@ -0,0 +1,279 @@
+use crate::app_state::AppState;
+use crate::game::army::{Army, ARMY_COST, ARMY_STRENGTH};
+use crate::game::city::CityId;
+use crate::game::diplomacy::{accept, declare_war, may_enter, propose, reject, Offer, TradeItem};
+use crate::game::player::PlayerId;
+use crate::game::replay::Baseline;
+use crate::game::spy::{sabotage, Spy, SPY_COST};
+use crate::game::turn::end_turn;
+use crate::game::units::UnitId;
+use crate::geo::crs::Bng;
+use crate::geo::data::GeoWithPathAndCities;
+use crate::geo::roads::NodeId;
+use serde::{Deserialize, Serialize};
+
+/// An order from a player, whether it came from the mouse, an AI or elsewhere.
+/// Every change a player makes to the game goes through here.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Command {
+    RaiseArmy {
+        player: PlayerId,
+        city: CityId,
+    },
+    RecruitSpy {
+        player: PlayerId,
+        city: CityId,
+    },
+    MoveUnit {
+        player: PlayerId,
+        unit: UnitId,
+        destination: NodeId,
+    },
+    Sabotage {
+        player: PlayerId,
+        unit: UnitId,
+    },
+    EndTurn {
+        player: PlayerId,
+    },
+    DeclareWar {
+        player: PlayerId,
+        target: PlayerId,
+    },
+    ProposePeace {
+        player: PlayerId,
+        target: PlayerId,
+    },
+    ProposeAlliance {
+        player: PlayerId,
+        target: PlayerId,
+    },
+    OfferTrade {
+        player: PlayerId,
+        target: PlayerId,
+        give: Vec<TradeItem>,
+        take: Vec<TradeItem>,
+    },
+    // Answers to proposals, by id
+    AcceptProposal {
+        player: PlayerId,
+        proposal: usize,
+    },
+    RejectProposal {
+        player: PlayerId,
+        proposal: usize,
+    },
+    // Debugging, hands a city straight over. Not in release builds
+    #[cfg(debug_assertions)]
+    TakeCity {
+        player: PlayerId,
+        city: CityId,
+    },
+}
+
+impl Command {
+    pub fn player(&self) -> PlayerId {
+        match self {
+            Command::RaiseArmy {
+                player,
+                ..
+            }
+            | Command::RecruitSpy {
+                player,
+                ..
+            }
+            | Command::MoveUnit {
+                player,
+                ..
+            }
+            | Command::Sabotage {
+                player,
+                ..
+            }
+            | Command::EndTurn {
+                player,
+            }
+            | Command::DeclareWar {
+                player,
+                ..
+            }
+            | Command::ProposePeace {
+                player,
+                ..
+            }
+            | Command::ProposeAlliance {
+                player,
+                ..
+            }
+            | Command::OfferTrade {
+                player,
+                ..
+            }
+            | Command::AcceptProposal {
+                player,
+                ..
+            }
+            | Command::RejectProposal {
+                player,
+                ..
+            } => *player,
+            #[cfg(debug_assertions)]
+            Command::TakeCity {
+                player,
+                ..
+            } => *player,
+        }
+    }
+}
+
+// Carry out a command if it's that player's turn and they're allowed to, returns false if it was refused.
+// Those carried out are recorded for replays.
+pub fn execute(app_state: &mut AppState, geo_and_cities: &GeoWithPathAndCities, command: Command) -> bool {
+    let recorded = command.clone();
+    let done = apply(app_state, geo_and_cities, command);
+    if done {
+        let ended = matches!(recorded, Command::EndTurn { .. });
+        app_state.recording.commands.push(recorded);
+        // Whoever plays next over the network is checked against the state they were handed
+        if ended && !app_state.remote_players.is_empty() {
+            app_state.baselines.push(Baseline::of(app_state));
+        }
+    }
+    done
+}
+
+fn apply(app_state: &mut AppState, geo_and_cities: &GeoWithPathAndCities, command: Command) -> bool {
+    let player = command.player();
+    if app_state.turn.current_player != Some(player) {
+        return false;
+    }
+    let turn = app_state.turn.turn;
+    match command {
+        Command::RaiseArmy {
+            city,
+            ..
+        } => match recruit(app_state, geo_and_cities, player, city, ARMY_COST) {
+            Some((name, node)) => {
+                let name = format!("{} Army", name);
+                app_state.messages.add(turn, format!("{} raised", name));
+                app_state.units.add(|id| Army::new(id, name, player, ARMY_STRENGTH, node));
+                true
+            }
+            None => false,
+        },
+        Command::RecruitSpy {
+            city,
+            ..
+        } => match recruit(app_state, geo_and_cities, player, city, SPY_COST) {
+            Some((name, node)) => {
+                let id = app_state.units.add(|id| Spy::new(id, format!("Agent {:03}", id + 1), player, node));
+                app_state.messages.add(turn, format!("Agent {:03} recruited in {}", id + 1, name));
+                true
+            }
+            None => false,
+        },
+        Command::MoveUnit {
+            unit,
+            destination,
+            ..
+        } => {
+            let roads = &geo_and_cities.roads;
+            let route = match app_state.units.get(unit).filter(|unit| unit.owner() == player) {
+                Some(unit) => match roads.shortest_path(unit.movement().node, destination) {
+                    Some(route) => route,
+                    None => {
+                        app_state.messages.add(turn, format!("{} can't get there by road", unit.get_name()));
+                        return false;
+                    }
+                },
+                None => return false,
+            };
+            // Not even passing beyond the edge on the way
+            if app_state.bounds.is_some_and(|bounds| route.nodes.iter().any(|&node| !bounds.contains(roads.nodes[node].bng()))) {
+                app_state.messages.add(turn, String::from("That's beyond the edge of the map"));
+                return false;
+            }
+            if let Err(message) = may_enter(app_state, geo_and_cities, player, &route.nodes) {
+                app_state.messages.add(turn, message);
+                return false;
+            }
+            match app_state.units.get_mut(unit) {
+                Some(unit) => {
+                    unit.movement_mut().follow(route);
+                    true
+                }
+                None => false,
+            }
+        }
+        Command::Sabotage {
+            unit,
+            ..
+        } => match app_state.units.get_as::<Spy>(unit) {
+            Some(spy) if spy.owner == player => {
+                sabotage(app_state, geo_and_cities, unit);
+                true
+            }
+            _ => false,
+        },
+        Command::EndTurn {
+            ..
+        } => {
+            end_turn(app_state, geo_and_cities);
+            true
+        }
+        Command::DeclareWar {
+            target,
+            ..
+        } => declare_war(app_state, player, target),
+        Command::ProposePeace {
+            target,
+            ..
+        } => propose(app_state, player, target, Offer::Peace),
+        Command::ProposeAlliance {
+            target,
+            ..
+        } => propose(app_state, player, target, Offer::Alliance),
+        Command::OfferTrade {
+            target,
+            give,
+            take,
+            ..
+        } => propose(
+            app_state,
+            player,
+            target,
+            Offer::Trade {
+                give,
+                take,
+            },
+        ),
+        Command::AcceptProposal {
+            proposal,
+            ..
+        } => accept(app_state, player, proposal),
+        Command::RejectProposal {
+            proposal,
+            ..
+        } => reject(app_state, player, proposal),
+        #[cfg(debug_assertions)]
+        Command::TakeCity {
+            city,
+            ..
+        } => app_state.transfer_city(city, player),
+    }
+}
+
+// Pay for a new unit at one of the player's cities, returning the city name and road node to put it on
+fn recruit(app_state: &mut AppState, geo_and_cities: &GeoWithPathAndCities, player: PlayerId, city: CityId, cost: f64) -> Option<(String, NodeId)> {
+    if app_state.owner_of(city) != Some(player) {
+        return None;
+    }
+    if app_state.players[player].production < cost {
+        app_state.messages.add(app_state.turn.turn, format!("{} needs {} production", app_state.players[player].name, cost));
+        return None;
+    }
+    let location = &geo_and_cities.cities[city];
+    let node = geo_and_cities.roads.nearest_node(Bng::from_scaled(location.x, location.y).to_world())?;
+    app_state.players[player].production -= cost;
+    Some((location.name.clone(), node))
+}
@@ -0,0 +1,269 @@
+use crate::app_state::AppState;
+use crate::game::city::CityId;
+use crate::game::player::PlayerId;
+use crate::geo::crs::Bng;
+use crate::geo::data::GeoWithPathAndCities;
+use crate::geo::roads::NodeId;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// Armies can't be sent within this distance (metres) of a city whose owner is at peace with them
+const BORDER_DISTANCE: f64 = 3000.0;
+
+// Unanswered proposals lapse after this many turns
+const PROPOSAL_TURNS: u32 = 1;
+
+// What a city is worth in trade, gold for every thousand people
+pub const CITY_PRICE: f64 = 20.0;
+
+// Sent as a gift from the diplomacy screen
+pub const GIFT: f64 = 50.0;
+
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum Relation {
+    War,
+    Peace,
+    Alliance,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TradeItem {
+    Gold(f64),
+    Production(f64),
+    City(CityId),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Offer {
+    Peace,
+    Alliance,
+    // What the proposer gives and what they want back
+    Trade {
+        give: Vec<TradeItem>,
+        take: Vec<TradeItem>,
+    },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Proposal {
+    pub id: usize,
+    pub turn: u32,
+    pub from: PlayerId,
+    pub to: PlayerId,
+    pub offer: Offer,
+}
+
+impl Proposal {
+    pub fn describe(&self, app_state: &AppState) -> String {
+        let (from, to) = (&app_state.players[self.from].name, &app_state.players[self.to].name);
+        match &self.offer {
+            Offer::Peace => format!("{} offers {} peace", from, to),
+            Offer::Alliance => format!("{} offers {} an alliance", from, to),
+            Offer::Trade {
+                give,
+                take,
+            } => format!("{} offers {} {} for {}", from, to, describe_items(app_state, give), describe_items(app_state, take)),
+        }
+    }
+}
+
+fn describe_items(app_state: &AppState, items: &[TradeItem]) -> String {
+    if items.is_empty() {
+        return String::from("nothing");
+    }
+    let items: Vec<String> = items
+        .iter()
+        .map(|item| match item {
+            TradeItem::Gold(gold) => format!("{:.0} gold", gold),
+            TradeItem::Production(production) => format!("{:.0} production", production),
+            TradeItem::City(city) => app_state.location(*city).name.clone(),
+        })
+        .collect();
+    items.join(", ")
+}
+
+/// How each pair of players stands. Everyone starts at war, and unowned cities are always fair game.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Diplomacy {
+    relations: BTreeMap<(PlayerId, PlayerId), Relation>,
+    pub proposals: Vec<Proposal>,
+    next_id: usize,
+}
+
+impl Diplomacy {
+    fn key(a: PlayerId, b: PlayerId) -> (PlayerId, PlayerId) {
+        (a.min(b), a.max(b))
+    }
+
+    pub fn relation(&self, a: PlayerId, b: PlayerId) -> Relation {
+        self.relations.get(&Diplomacy::key(a, b)).copied().unwrap_or(Relation::War)
+    }
+
+    pub fn set_relation(&mut self, a: PlayerId, b: PlayerId, relation: Relation) {
+        self.relations.insert(Diplomacy::key(a, b), relation);
+    }
+
+    // Whether their armies fight and their cities can be taken. Player 0 holds the unowned cities.
+    pub fn is_hostile(&self, a: PlayerId, b: PlayerId) -> bool {
+        a != b && (a == 0 || b == 0 || self.relation(a, b) == Relation::War)
+    }
+
+    pub fn are_allies(&self, a: PlayerId, b: PlayerId) -> bool {
+        a != b && a != 0 && b != 0 && self.relation(a, b) == Relation::Alliance
+    }
+
+    pub fn propose(&mut self, turn: u32, from: PlayerId, to: PlayerId, offer: Offer) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.proposals.push(Proposal {
+            id,
+            turn,
+            from,
+            to,
+            offer,
+        });
+        id
+    }
+
+    pub fn take_proposal(&mut self, id: usize, to: PlayerId) -> Option<Proposal> {
+        let index = self.proposals.iter().position(|proposal| proposal.id == id && proposal.to == to)?;
+        Some(self.proposals.remove(index))
+    }
+
+    pub fn proposals_to(&self, player: PlayerId) -> impl Iterator<Item = &Proposal> {
+        self.proposals.iter().filter(move |proposal| proposal.to == player)
+    }
+
+    // At the end of the turn
+    pub fn expire(&mut self, turn: u32) {
+        self.proposals.retain(|proposal| proposal.turn + PROPOSAL_TURNS > turn);
+    }
+}
+
+// Armies may go anywhere except near the cities of those they're at peace with, on the way or at the end
+pub fn may_enter(app_state: &AppState, geo_and_cities: &GeoWithPathAndCities, player: PlayerId, nodes: &[NodeId]) -> Result<(), String> {
+    let peaceful: Vec<(CityId, PlayerId)> = (0..geo_and_cities.cities.len())
+        .filter_map(|city| app_state.owner_of(city).map(|owner| (city, owner)))
+        .filter(|&(_, owner)| owner != player && owner != 0 && app_state.diplomacy.relation(player, owner) == Relation::Peace)
+        .collect();
+    let blocked = nodes.iter().find_map(|&node| {
+        let at = geo_and_cities.roads.nodes[node].bng();
+        peaceful.iter().find(|(city, _)| {
+            let location = &geo_and_cities.cities[*city];
+            Bng::from_scaled(location.x, location.y).distance(at) <= BORDER_DISTANCE
+        })
+    });
+    match blocked {
+        Some(&(city, owner)) => Err(format!(
+            "{} is at peace with {}, so can't march on {}",
+            app_state.players[player].name, app_state.players[owner].name, geo_and_cities.cities[city].name
+        )),
+        None => Ok(()),
+    }
+}
+
+// Everything on offer is still there to be handed over
+fn can_give(app_state: &AppState, player: PlayerId, items: &[TradeItem]) -> bool {
+    items.iter().all(|item| match item {
+        TradeItem::Gold(gold) => app_state.players[player].treasury >= *gold,
+        TradeItem::Production(production) => app_state.players[player].production >= *production,
+        TradeItem::City(city) => app_state.owner_of(*city) == Some(player),
+    })
+}
+
+// A negative or NaN amount would run the trade backwards, or slip past can_give
+fn valid_amounts(items: &[TradeItem]) -> bool {
+    items.iter().all(|item| match item {
+        TradeItem::Gold(amount) | TradeItem::Production(amount) => amount.is_finite() && *amount > 0.0,
+        TradeItem::City(_) => true,
+    })
+}
+
+fn hand_over(app_state: &mut AppState, from: PlayerId, to: PlayerId, items: &[TradeItem]) {
+    for item in items {
+        match item {
+            TradeItem::Gold(gold) => {
+                app_state.players[from].treasury -= gold;
+                app_state.players[to].treasury += gold;
+            }
+            TradeItem::Production(production) => {
+                app_state.players[from].production -= production;
+                app_state.players[to].production += production;
+            }
+            TradeItem::City(city) => {
+                app_state.transfer_city(*city, to);
+            }
+        }
+    }
+}
+
+// The recipient agrees, returns false if the proposal has gone or can no longer be honoured
+pub fn accept(app_state: &mut AppState, player: PlayerId, id: usize) -> bool {
+    let turn = app_state.turn.turn;
+    let proposal = match app_state.diplomacy.take_proposal(id, player) {
+        Some(proposal) => proposal,
+        None => return false,
+    };
+    let description = proposal.describe(app_state);
+    match &proposal.offer {
+        Offer::Peace => app_state.diplomacy.set_relation(proposal.from, proposal.to, Relation::Peace),
+        Offer::Alliance => app_state.diplomacy.set_relation(proposal.from, proposal.to, Relation::Alliance),
+        Offer::Trade {
+            give,
+            take,
+        } => {
+            if !can_give(app_state, proposal.from, give) || !can_give(app_state, proposal.to, take) {
+                app_state.messages.add(turn, format!("Trade fell through: {}", description));
+                return false;
+            }
+            hand_over(app_state, proposal.from, proposal.to, give);
+            hand_over(app_state, proposal.to, proposal.from, take);
+        }
+    }
+    app_state.messages.add(turn, format!("Accepted: {}", description));
+    true
+}
+
+pub fn reject(app_state: &mut AppState, player: PlayerId, id: usize) -> bool {
+    match app_state.diplomacy.take_proposal(id, player) {
+        Some(proposal) => {
+            let message = format!("Rejected: {}", proposal.describe(app_state));
+            app_state.messages.add(app_state.turn.turn, message);
+            true
+        }
+        None => false,
+    }
+}
+
+pub fn declare_war(app_state: &mut AppState, player: PlayerId, target: PlayerId) -> bool {
+    if player == target || target == 0 || !app_state.players[target].takes_turns() || app_state.diplomacy.relation(player, target) == Relation::War {
+        return false;
+    }
+    app_state.diplomacy.set_relation(player, target, Relation::War);
+    app_state.diplomacy.proposals.retain(|proposal| !(proposal.from == player && proposal.to == target || proposal.from == target && proposal.to == player));
+    let message = format!("{} declared war on {}", app_state.players[player].name, app_state.players[target].name);
+    app_state.messages.add(app_state.turn.turn, message);
+    true
+}
+
+pub fn propose(app_state: &mut AppState, player: PlayerId, target: PlayerId, offer: Offer) -> bool {
+    if player == target || target == 0 || !app_state.players[target].takes_turns() {
+        return false;
+    }
+    let pointless = match offer {
+        Offer::Peace => app_state.diplomacy.relation(player, target) != Relation::War,
+        Offer::Alliance => app_state.diplomacy.relation(player, target) == Relation::Alliance,
+        Offer::Trade {
+            ref give,
+            ref take,
+        } => (give.is_empty() && take.is_empty()) || !valid_amounts(give) || !valid_amounts(take) || !can_give(app_state, player, give),
+    };
+    if pointless {
+        return false;
+    }
+    let turn = app_state.turn.turn;
+    let id = app_state.diplomacy.propose(turn, player, target, offer);
+    let message = app_state.diplomacy.proposals.iter().find(|proposal| proposal.id == id).map(|proposal| proposal.describe(app_state)).unwrap_or_default();
+    app_state.messages.add(turn, message);
+    true
+}
@@ -0,0 +1,78 @@
+use crate::app_state::AppState;
+use serde::{Deserialize, Serialize};
+
+// Per thousand people, per turn
+const INCOME_PER_THOUSAND: f64 = 1.0;
+const PRODUCTION_PER_THOUSAND: f64 = 0.5;
+
+// Growth per turn while small, tailing off as the city nears its capacity
+const GROWTH_RATE: f64 = 0.02;
+const CAPACITY_FACTOR: f64 = 2.0;
+
+// Share of output lost while sabotaged
+const SABOTAGE_LOSS: f64 = 0.5;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Economy {
+    pub population: f64,
+    pub capacity: f64,
+    pub production: f64,
+    pub income: f64,
+    pub growth: f64,
+    // Turns of sabotage left
+    pub sabotaged: u32,
+}
+
+impl Economy {
+    pub fn new(population: i32) -> Economy {
+        let mut economy = Economy {
+            population: population as f64,
+            capacity: population as f64 * CAPACITY_FACTOR,
+            production: 0.0,
+            income: 0.0,
+            growth: 0.0,
+            sabotaged: 0,
+        };
+        economy.update();
+        economy
+    }
+
+    // Derive this turn's output from the current population
+    pub fn update(&mut self) {
+        let thousands = self.population / 1000.0;
+        self.income = thousands * INCOME_PER_THOUSAND;
+        self.production = thousands * PRODUCTION_PER_THOUSAND;
+        if self.sabotaged > 0 {
+            self.income *= 1.0 - SABOTAGE_LOSS;
+            self.production *= 1.0 - SABOTAGE_LOSS;
+        }
+        self.growth = self.population * GROWTH_RATE * (1.0 - self.population / self.capacity).max(0.0);
+    }
+
+    pub fn grow(&mut self) {
+        self.population += self.growth;
+        self.sabotaged = self.sabotaged.saturating_sub(1);
+        self.update();
+    }
+}
+
+// Each city pays its owner then grows, and each unit is paid for
+pub fn collect_income(app_state: &mut AppState) {
+    let turn = app_state.turn.turn;
+    for (id, player) in app_state.players.iter_mut().enumerate().filter(|(_, p)| p.takes_turns()) {
+        let (mut income, mut production) = (0.0, 0.0);
+        for &city in player.cities.iter() {
+            let economy = &mut app_state.cities[city].economy;
+            income += economy.income;
+            production += economy.production;
+            economy.grow();
+        }
+        let upkeep = app_state.units.upkeep(id);
+        player.treasury += income - upkeep;
+        player.production += production;
+        app_state.messages.add(turn, format!("{} collected {:.0} gold and {:.0} production, paid {:.0} upkeep", player.name, income, production, upkeep));
+        if player.treasury < 0.0 {
+            app_state.messages.add(turn, format!("{} is in debt", player.name));
+        }
+    }
+}
@@ -0,0 +1,15 @@
+use crate::game::city::CityId;
+use crate::game::player::PlayerId;
+use crate::game::units::UnitId;
+
+pub enum GameEvent {
+    OwnershipChanged {
+        city: CityId,
+        from: PlayerId,
+        to: PlayerId,
+    },
+    // Once the turn's moves have been made
+    UnitMoved {
+        unit: UnitId,
+    },
+}
@@ -0,0 +1,75 @@
+use crate::app_state::AppState;
+use crate::game::player::PlayerId;
+use crate::geo::crs::World;
+use crate::geo::roads::RoadGraph;
+use crate::gfx::skia::Skia;
+use skia_safe::paint::Style;
+use skia_safe::{BlendMode, ClipOp, Color, Paint, Path, Point};
+
+// How far around its own cities a player can see, km
+pub const CITY_VISION: f32 = 15.0;
+
+// What one player can see, as circles in canvas units around cities and units' road nodes
+pub struct Vision {
+    pub player: PlayerId,
+    sight: Vec<(World, f32)>,
+    intel: Vec<(World, f32)>,
+}
+
+impl Vision {
+    pub fn new(app_state: &AppState, player: PlayerId, roads: &RoadGraph) -> Vision {
+        // Allies share what their cities and units can see, but not their spies' intel
+        let sharing = |owner: PlayerId| owner == player || app_state.diplomacy.are_allies(player, owner);
+        let mut sight: Vec<(World, f32)> = (0..app_state.players.len())
+            .filter(|&owner| sharing(owner))
+            .flat_map(|owner| app_state.players[owner].cities.iter())
+            .map(|&city| (app_state.location(city).world(), CITY_VISION))
+            .collect();
+        sight.extend(app_state.units.iter().filter(|unit| sharing(unit.owner())).map(|unit| (unit.at(roads), unit.vision_radius())));
+        let intel: Vec<(World, f32)> =
+            app_state.units.iter().filter(|unit| unit.owner() == player && unit.gathers_intel()).map(|unit| (unit.at(roads), unit.vision_radius())).collect();
+
+        Vision {
+            player,
+            sight,
+            intel,
+        }
+    }
+
+    pub fn can_see(&self, point: World) -> bool {
+        within(&self.sight, point)
+    }
+
+    // Close enough to a spy to know the details
+    pub fn has_intel(&self, point: World) -> bool {
+        within(&self.intel, point)
+    }
+}
+
+fn within(circles: &[(World, f32)], point: World) -> bool {
+    circles.iter().any(|(centre, radius)| Point::distance(centre.to_point(), point.to_point()) <= *radius)
+}
+
+// Everything out of sight is washed out and darkened
+pub fn draw_fog(skia: &mut Skia, vision: &Vision) {
+    let mut visible = Path::new();
+    vision.sight.iter().for_each(|(centre, radius)| {
+        visible.add_circle(centre.to_point(), *radius, None);
+    });
+
+    let mut paint_desaturate = Paint::default();
+    paint_desaturate.set_style(Style::Fill);
+    paint_desaturate.set_color(Color::GRAY);
+    paint_desaturate.set_blend_mode(BlendMode::Saturation);
+
+    let mut paint_darken = Paint::default();
+    paint_darken.set_style(Style::Fill);
+    paint_darken.set_color(Color::from_argb(110, 0, 0, 0));
+
+    let canvas = skia.get_canvas();
+    canvas.save();
+    canvas.clip_path(&visible, ClipOp::Difference, true);
+    canvas.draw_paint(&paint_desaturate);
+    canvas.draw_paint(&paint_darken);
+    canvas.restore();
+}
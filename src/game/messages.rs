@@ -0,0 +1,41 @@
+use crate::game::player::PlayerId;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Message {
+    pub turn: u32,
+    // Only this player sees it, everyone if None
+    pub to: Option<PlayerId>,
+    pub text: String,
+}
+
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct MessageLog {
+    pub entries: Vec<Message>,
+}
+
+impl MessageLog {
+    pub fn add(&mut self, turn: u32, message: String) {
+        self.entries.push(Message {
+            turn,
+            to: None,
+            text: message,
+        });
+    }
+
+    // For one player's eyes only
+    pub fn add_for(&mut self, turn: u32, player: PlayerId, message: String) {
+        self.entries.push(Message {
+            turn,
+            to: Some(player),
+            text: message,
+        });
+    }
+
+    // The last few messages this viewer may see, oldest first
+    pub fn recent(&self, viewer: Option<PlayerId>, count: usize) -> Vec<&Message> {
+        let mut visible: Vec<&Message> = self.entries.iter().rev().filter(|message| message.to.is_none() || message.to == viewer).take(count).collect();
+        visible.reverse();
+        visible
+    }
+}
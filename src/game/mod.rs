@@ -1,5 +1,23 @@
+pub mod ai;
+pub mod army;
 pub mod city;
+pub mod combat;
+pub mod command;
+pub mod diplomacy;
+pub mod economy;
+pub mod events;
+pub mod fog;
+pub mod messages;
+pub mod movement;
 pub mod player;
-mod units;
-mod spy;
-mod army;
+pub mod random;
+pub mod replay;
+pub mod save;
+pub mod scenario;
+pub mod script;
+pub mod setup;
+pub mod spy;
+pub mod supply;
+pub mod turn;
+pub mod units;
+pub mod victory;
@@ -0,0 +1,111 @@
+use crate::geo::crs::World;
+use crate::geo::roads::{EdgeId, NodeId, RoadGraph, Route};
+use serde::{Deserialize, Serialize};
+use skia_safe::{Path, Point};
+use std::collections::VecDeque;
+use std::time::Instant;
+
+pub const ANIMATION_SECS: f32 = 1.5;
+
+// A unit's place on the road graph and where it's been told to go
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Movement {
+    pub node: NodeId,
+    pub route: VecDeque<EdgeId>,
+    // Seconds of driving per turn, and how much was left over after the last move
+    pub points: f64,
+    pub remaining: f64,
+    pub moved: bool,
+    // Where the unit went last turn, played back on screen
+    #[serde(skip)]
+    trail: Vec<World>,
+    #[serde(skip)]
+    moved_at: Option<Instant>,
+}
+
+impl Movement {
+    pub fn new(node: NodeId, points: f64) -> Movement {
+        Movement {
+            node,
+            route: VecDeque::new(),
+            points,
+            remaining: points,
+            moved: false,
+            trail: Vec::new(),
+            moved_at: None,
+        }
+    }
+
+    // A route from where the unit is now
+    pub fn follow(&mut self, route: Route) {
+        self.route = route.edges.into();
+    }
+
+    // Follow the route as far as this turn's movement allows, a link is never split but the first is always taken
+    pub fn advance(&mut self, roads: &RoadGraph) {
+        self.remaining = self.points;
+        self.trail = vec![roads.nodes[self.node].bng().to_world()];
+        let mut moved = false;
+        while let Some(&edge_id) = self.route.front() {
+            let edge = &roads.edges[edge_id];
+            let cost = edge.time();
+            if cost.is_infinite() || (moved && cost > self.remaining) {
+                break;
+            }
+            self.trail.extend(edge.points_from(self.node));
+            self.remaining = (self.remaining - cost).max(0.0);
+            self.node = edge.other_end(self.node);
+            self.route.pop_front();
+            moved = true;
+        }
+        self.moved = moved;
+        self.moved_at = moved.then(Instant::now);
+    }
+
+    // Where the rules have the unit, whatever the screen is still showing
+    pub fn at(&self, roads: &RoadGraph) -> World {
+        roads.nodes[self.node].bng().to_world()
+    }
+
+    // Where it's drawn, along the trail for a moment after it moves. Never for game logic, it depends on the clock
+    pub fn position(&self, roads: &RoadGraph) -> World {
+        let end = self.at(roads);
+        let elapsed = match self.moved_at {
+            Some(moved_at) => moved_at.elapsed().as_secs_f32(),
+            None => return end,
+        };
+        if elapsed >= ANIMATION_SECS || self.trail.len() < 2 {
+            return end;
+        }
+
+        // Walk along the trail to the same fraction of its length
+        let lengths: Vec<f32> = self.trail.windows(2).map(|w| Point::distance(w[0].to_point(), w[1].to_point())).collect();
+        let mut remaining = lengths.iter().sum::<f32>() * elapsed / ANIMATION_SECS;
+        for (w, length) in self.trail.windows(2).zip(lengths) {
+            if remaining <= length && length > 0.0 {
+                let t = remaining / length;
+                return World::new(w[0].x + (w[1].x - w[0].x) * t, w[0].y + (w[1].y - w[0].y) * t);
+            }
+            remaining -= length;
+        }
+        end
+    }
+
+    pub fn is_moving(&self) -> bool {
+        !self.route.is_empty()
+    }
+
+    // Planned route, from where the unit will be once any animation finishes
+    pub fn route_path(&self, roads: &RoadGraph) -> Path {
+        let mut path = Path::new();
+        let mut node = self.node;
+        let mut points: Vec<Point> = vec![roads.nodes[node].bng().to_world().to_point()];
+        self.route.iter().for_each(|&edge_id| {
+            let edge = &roads.edges[edge_id];
+            points.extend(edge.points_from(node).iter().map(|p| p.to_point()));
+            node = edge.other_end(node);
+        });
+        path.add_poly(&points, false);
+        path
+    }
+}
@@ -1,34 +1,53 @@
-use crate::game::city::City;
-use crate::geo::data::GeoWithPathAndCities;
-use std::rc::Rc;
+use crate::game::ai::Strategy;
+use crate::game::city::CityId;
+use crate::game::turn::Phase;
+use skia_safe::Color;
+
+// Index into AppState players
+pub type PlayerId = usize;
 
 pub enum PlayerType {
     Player,
+    Ai(Box<dyn Strategy>),
     NotAssigned,
 }
 
 pub struct Player {
+    pub name: String,
     pub player_type: PlayerType,
-    pub cities: Vec<Rc<City>>,
+    pub phase: Phase,
+    pub colour: Color,
+    pub treasury: f64,
+    pub production: f64,
+    pub cities: Vec<CityId>,
+    // Where they started, for the capitals victory
+    pub capital: Option<CityId>,
+    // No cities and no units left
+    pub eliminated: bool,
 }
 
 impl Player {
-    pub fn new(player_type: PlayerType) -> Player {
+    pub fn new(name: &str, player_type: PlayerType, colour: Color) -> Player {
         Player {
+            name: name.to_string(),
             player_type,
+            phase: Phase::Resolution,
+            colour,
+            treasury: 0.0,
+            production: 0.0,
             cities: Vec::new(),
+            capital: None,
+            eliminated: false,
         }
     }
 
-    pub fn assign_all(&mut self, geo_and_cities: &GeoWithPathAndCities) {
-        for city in geo_and_cities.cities.iter() {
-            self.cities.push(Rc::new(City {
-                location: city.clone(),
-            }));
-        }
+    pub fn add_city(&mut self, city: CityId) {
+        self.cities.push(city);
     }
 
-    pub fn change_ownership(&mut self, city: Rc<City>) {
-        self.cities.push(city);
+    pub fn remove_city(&mut self, city: CityId) -> bool {
+        let before = self.cities.len();
+        self.cities.retain(|&c| c != city);
+        self.cities.len() != before
     }
 }
@@ -0,0 +1,260 @@
+use crate::app_state::AppState;
+use crate::game::city::{City, CityId};
+use crate::game::command::{execute, Command};
+use crate::game::diplomacy::Diplomacy;
+use crate::game::player::PlayerId;
+use crate::game::random::GameRng;
+use crate::game::save::SavedUnit;
+use crate::game::setup::{new_game, GameSetup};
+use crate::game::turn::Phase;
+use crate::geo::data::GeoWithPathAndCities;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::{create_dir_all, File};
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+pub const REPLAY_VERSION: u32 = 1;
+
+pub const REPLAY_FILE: &str = "saves/replay.cbor";
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Enough to play a game again exactly: how it was set up, including the seed,
+/// every order that was carried out, and a hash of the state as each turn began.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Recording {
+    pub version: u32,
+    pub setup: GameSetup,
+    pub commands: Vec<Command>,
+    pub hashes: Vec<(u32, u64)>,
+}
+
+// Also what saves from before recordings get, so what they go on to record can be read back
+impl Default for Recording {
+    fn default() -> Recording {
+        Recording {
+            version: REPLAY_VERSION,
+            setup: GameSetup::default(),
+            commands: Vec::new(),
+            hashes: Vec::new(),
+        }
+    }
+}
+
+// The parts of the game that orders and randomness change, leaving out the view and the messages
+#[derive(Serialize)]
+struct HashedState<'a> {
+    turn: u32,
+    current_player: Option<usize>,
+    players: Vec<(Phase, f64, f64, &'a [CityId])>,
+    cities: &'a [City],
+    units: Vec<SavedUnit>,
+    rng: &'a GameRng,
+    diplomacy: &'a Diplomacy,
+}
+
+// FNV-1a over the CBOR encoding, so it's the same from build to build
+pub fn state_hash(app_state: &AppState) -> u64 {
+    let state = HashedState {
+        turn: app_state.turn.turn,
+        current_player: app_state.turn.current_player,
+        players: app_state.players.iter().map(|player| (player.phase, player.treasury, player.production, player.cities.as_slice())).collect(),
+        cities: &app_state.cities,
+        units: app_state.units.iter().map(|unit| unit.saved()).collect(),
+        rng: &app_state.rng,
+        diplomacy: &app_state.diplomacy,
+    };
+    let bytes = serde_cbor::to_vec(&state).unwrap_or_default();
+    bytes.iter().fold(FNV_OFFSET, |hash, &byte| (hash ^ byte as u64).wrapping_mul(FNV_PRIME))
+}
+
+/// Where a player's orders begin among the recorded commands, and a hash of the state they
+/// were handed. Networked games send it with the orders so peers can check they agree.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Baseline {
+    pub commands: usize,
+    pub turn: u32,
+    pub player: Option<PlayerId>,
+    pub hash: u64,
+}
+
+impl Baseline {
+    pub fn of(app_state: &AppState) -> Baseline {
+        Baseline {
+            commands: app_state.recording.commands.len(),
+            turn: app_state.turn.turn,
+            player: app_state.turn.current_player,
+            hash: state_hash(app_state),
+        }
+    }
+}
+
+// Note the state as a turn begins
+pub fn record_turn(app_state: &mut AppState) {
+    let hash = state_hash(app_state);
+    app_state.recording.hashes.push((app_state.turn.turn, hash));
+}
+
+pub fn save_recording(recording: &Recording, path: &str) -> Result<(), Box<dyn Error>> {
+    if let Some(parent) = Path::new(path).parent() {
+        create_dir_all(parent)?;
+    }
+    let writer = BufWriter::new(File::create(path)?);
+    serde_cbor::to_writer(writer, recording)?;
+    Ok(())
+}
+
+pub fn load_recording(path: &str) -> Result<Recording, Box<dyn Error>> {
+    let reader = BufReader::new(File::open(path)?);
+    let recording: Recording = serde_cbor::from_reader(reader)?;
+    if recording.version != REPLAY_VERSION {
+        return Err(format!("Replay is version {}, this game reads version {}", recording.version, REPLAY_VERSION).into());
+    }
+    Ok(recording)
+}
+
+// F6, the game so far
+pub fn save_replay(app_state: &mut AppState) {
+    let message = match save_recording(&app_state.recording, REPLAY_FILE) {
+        Ok(()) => format!("Replay saved to {}", REPLAY_FILE),
+        Err(e) => format!("Failed to save replay: {}", e),
+    };
+    app_state.messages.add(app_state.turn.turn, message);
+}
+
+/// Set the game up again and carry out every recorded order, checking the state hash at the
+/// start of each turn. Returns the number of turns verified, or where the replay went wrong.
+pub fn verify_replay(recording: &Recording, geo_and_cities: &GeoWithPathAndCities) -> Result<usize, Box<dyn Error>> {
+    let mut app_state = new_game(&recording.setup, geo_and_cities)?;
+    for (index, command) in recording.commands.iter().enumerate() {
+        if !execute(&mut app_state, geo_and_cities, command.clone()) {
+            return Err(format!("Order {} on turn {} was refused: {:?}", index + 1, app_state.turn.turn, command).into());
+        }
+        app_state.process_events();
+    }
+
+    let replayed = &app_state.recording.hashes;
+    for (turn, hash) in &recording.hashes {
+        match replayed.iter().find(|(t, _)| t == turn) {
+            Some((_, h)) if h == hash => {}
+            Some((_, h)) => return Err(format!("Turn {} differs: recorded {:016x}, replayed {:016x}", turn, hash, h).into()),
+            None => return Err(format!("Turn {} was never reached", turn).into()),
+        }
+    }
+    Ok(recording.hashes.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::ai::take_ai_turn;
+    use crate::game::movement::ANIMATION_SECS;
+    use crate::game::spy::{Spy, SPY_COST};
+    use crate::geo::data::test_geo;
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    const TURNS: u32 = 4;
+
+    // A few turns between computer players, recorded as they go
+    fn recorded_game(geo_and_cities: &GeoWithPathAndCities) -> Recording {
+        let mut setup = GameSetup::default();
+        setup.players.iter_mut().for_each(|player| player.human = false);
+        setup.seed = Some(11);
+        let mut app_state = new_game(&setup, geo_and_cities).unwrap();
+        while app_state.turn.turn <= TURNS && take_ai_turn(&mut app_state, geo_and_cities) {
+            app_state.process_events();
+        }
+        app_state.recording
+    }
+
+    #[test]
+    fn replays_match_every_recorded_turn() {
+        let geo_and_cities = test_geo();
+        let recording = recorded_game(&geo_and_cities);
+        assert!(!recording.commands.is_empty());
+        assert_eq!(verify_replay(&recording, &geo_and_cities).unwrap(), TURNS as usize + 1);
+    }
+
+    fn order(app_state: &mut AppState, geo_and_cities: &GeoWithPathAndCities, command: Command) {
+        assert!(execute(app_state, geo_and_cities, command.clone()), "{:?} was refused", command);
+        app_state.process_events();
+    }
+
+    fn end_turn(app_state: &mut AppState, geo_and_cities: &GeoWithPathAndCities) {
+        for player in 1..app_state.players.len() {
+            order(
+                app_state,
+                geo_and_cities,
+                Command::EndTurn {
+                    player,
+                },
+            );
+        }
+    }
+
+    // A spy sent into an enemy city and told to sabotage it once its move has long finished on screen.
+    // The replay gives the same orders straight away, so anything timed by the animation would differ
+    #[test]
+    fn replays_of_sabotage_match_whenever_they_are_played() {
+        let geo_and_cities = test_geo();
+        let mut setup = GameSetup::default();
+        setup.players.iter_mut().for_each(|player| player.human = true);
+        setup.seed = Some(5);
+        let mut app_state = new_game(&setup, &geo_and_cities).unwrap();
+        while app_state.players[1].production < SPY_COST {
+            assert!(app_state.turn.turn < 20, "never had the production for a spy");
+            end_turn(&mut app_state, &geo_and_cities);
+        }
+
+        let (home, target) = (app_state.players[1].cities[0], app_state.players[2].cities[0]);
+        order(
+            &mut app_state,
+            &geo_and_cities,
+            Command::RecruitSpy {
+                player: 1,
+                city: home,
+            },
+        );
+        let unit = app_state.units.of_type::<Spy>().map(|spy| spy.id).next().unwrap();
+        let destination = geo_and_cities.roads.nearest_node(app_state.location(target).world()).unwrap();
+        order(
+            &mut app_state,
+            &geo_and_cities,
+            Command::MoveUnit {
+                player: 1,
+                unit,
+                destination,
+            },
+        );
+        end_turn(&mut app_state, &geo_and_cities);
+        assert_eq!(app_state.units.get(unit).map(|spy| spy.movement().node), Some(destination));
+
+        sleep(Duration::from_secs_f32(ANIMATION_SECS + 0.1));
+        order(
+            &mut app_state,
+            &geo_and_cities,
+            Command::Sabotage {
+                player: 1,
+                unit,
+            },
+        );
+        let name = &app_state.location(target).name;
+        let acted = |text: &str| *text == format!("{} was sabotaged", name) || text.ends_with(&format!("was caught in {}", name));
+        assert!(app_state.messages.entries.iter().any(|message| acted(&message.text)), "the spy never got to work");
+        end_turn(&mut app_state, &geo_and_cities);
+
+        let recording = &app_state.recording;
+        assert_eq!(verify_replay(recording, &geo_and_cities).unwrap(), recording.hashes.len());
+    }
+
+    #[test]
+    fn replays_notice_a_turn_that_differs() {
+        let geo_and_cities = test_geo();
+        let mut recording = recorded_game(&geo_and_cities);
+        recording.hashes[2].1 ^= 1;
+        assert!(verify_replay(&recording, &geo_and_cities).is_err());
+    }
+}
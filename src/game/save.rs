@@ -0,0 +1,298 @@
+use crate::app_state::AppState;
+use crate::game::ai::strategy_named;
+use crate::game::army::Army;
+use crate::game::city::{City, CityId};
+use crate::game::combat::Battle;
+use crate::game::diplomacy::Diplomacy;
+use crate::game::messages::MessageLog;
+use crate::game::player::{Player, PlayerId, PlayerType};
+use crate::game::random::GameRng;
+use crate::game::replay::Recording;
+use crate::game::scenario::{Bounds, Trigger};
+use crate::game::script::{load_scripts, ScriptFlags};
+use crate::game::spy::Spy;
+use crate::game::turn::{Phase, TurnManager};
+use crate::game::units::{UnitId, UnitRegistry, UnitTrait};
+use crate::game::victory::{GameOver, VictoryConditions};
+use crate::geo::data::GeoWithPathAndCities;
+use serde::{Deserialize, Serialize};
+use skia_safe::Color;
+use std::error::Error;
+use std::fs::{create_dir_all, File};
+use std::io::{BufReader, BufWriter, Read};
+use std::path::Path;
+
+// Bump whenever SaveGame changes shape, and teach load_game to read the old one
+pub const SAVE_VERSION: u32 = 2;
+
+pub const QUICKSAVE: &str = "saves/quicksave.cbor";
+
+// Read first, so a save from a newer version is refused before parsing the rest
+#[derive(Deserialize)]
+struct Header {
+    version: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct SavedCamera {
+    pub x: f32,
+    pub y: f32,
+    pub zoom: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+enum SavedPlayerType {
+    Player,
+    // Strategy name
+    Ai(String),
+    NotAssigned,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedPlayer {
+    name: String,
+    player_type: SavedPlayerType,
+    phase: Phase,
+    colour: (u8, u8, u8),
+    treasury: f64,
+    production: f64,
+    cities: Vec<CityId>,
+    #[serde(default)]
+    capital: Option<CityId>,
+    #[serde(default)]
+    eliminated: bool,
+}
+
+// Every kind of unit, each one hands over its own variant through UnitTrait::saved
+#[derive(Serialize, Deserialize)]
+pub enum SavedUnit {
+    Army(Army),
+    Spy(Spy),
+}
+
+impl SavedUnit {
+    fn restore(self) -> Box<dyn UnitTrait> {
+        match self {
+            SavedUnit::Army(army) => Box::new(army),
+            SavedUnit::Spy(spy) => Box::new(spy),
+        }
+    }
+}
+
+// Everything needed to carry on a game. Cities and units are referred to by id,
+// the map itself isn't saved and is loaded as usual.
+#[derive(Serialize, Deserialize)]
+struct SaveGame {
+    version: u32,
+    players: Vec<SavedPlayer>,
+    cities: Vec<City>,
+    units: Vec<SavedUnit>,
+    next_unit: UnitId,
+    turn: TurnManager,
+    messages: MessageLog,
+    combat_log: Vec<Battle>,
+    selected_city: Option<CityId>,
+    selected_unit: Option<UnitId>,
+    camera: SavedCamera,
+    // Since version 2, older saves start these afresh
+    #[serde(default)]
+    rng: GameRng,
+    #[serde(default)]
+    recording: Recording,
+    #[serde(default)]
+    victory: VictoryConditions,
+    #[serde(default)]
+    game_over: Option<GameOver>,
+    #[serde(default)]
+    diplomacy: Diplomacy,
+    #[serde(default)]
+    bounds: Option<Bounds>,
+    #[serde(default)]
+    triggers: Vec<Trigger>,
+    #[serde(default)]
+    script_flags: ScriptFlags,
+}
+
+pub fn save_game(app_state: &AppState, camera: SavedCamera, path: &str) -> Result<(), Box<dyn Error>> {
+    let players = app_state
+        .players
+        .iter()
+        .map(|player| SavedPlayer {
+            name: player.name.clone(),
+            player_type: match &player.player_type {
+                PlayerType::Player => SavedPlayerType::Player,
+                PlayerType::Ai(strategy) => SavedPlayerType::Ai(strategy.name().to_string()),
+                PlayerType::NotAssigned => SavedPlayerType::NotAssigned,
+            },
+            phase: player.phase,
+            colour: (player.colour.r(), player.colour.g(), player.colour.b()),
+            treasury: player.treasury,
+            production: player.production,
+            cities: player.cities.clone(),
+            capital: player.capital,
+            eliminated: player.eliminated,
+        })
+        .collect();
+    let save = SaveGame {
+        version: SAVE_VERSION,
+        players,
+        cities: app_state.cities.clone(),
+        units: app_state.units.iter().map(|unit| unit.saved()).collect(),
+        next_unit: app_state.units.next_id(),
+        turn: app_state.turn.clone(),
+        messages: app_state.messages.clone(),
+        combat_log: app_state.combat_log.clone(),
+        selected_city: app_state.selected_city,
+        selected_unit: app_state.selected_unit,
+        camera,
+        rng: app_state.rng.clone(),
+        recording: app_state.recording.clone(),
+        victory: app_state.victory.clone(),
+        game_over: app_state.game_over.clone(),
+        diplomacy: app_state.diplomacy.clone(),
+        bounds: app_state.bounds,
+        triggers: app_state.triggers.clone(),
+        script_flags: app_state.script_flags.clone(),
+    };
+
+    if let Some(parent) = Path::new(path).parent() {
+        create_dir_all(parent)?;
+    }
+    let writer = BufWriter::new(File::create(path)?);
+    serde_cbor::to_writer(writer, &save)?;
+    Ok(())
+}
+
+pub fn load_game(path: &str, geo_and_cities: &GeoWithPathAndCities) -> Result<(AppState, SavedCamera), Box<dyn Error>> {
+    let mut bytes = Vec::new();
+    BufReader::new(File::open(path)?).read_to_end(&mut bytes)?;
+    let header: Header = serde_cbor::from_slice(&bytes)?;
+    let mut save: SaveGame = match header.version {
+        1 | SAVE_VERSION => serde_cbor::from_slice(&bytes)?,
+        version => return Err(format!("Save is version {}, this game reads version {}", version, SAVE_VERSION).into()),
+    };
+    if save.cities.len() != geo_and_cities.cities.len() {
+        return Err(format!("Save has {} cities but the map has {}", save.cities.len(), geo_and_cities.cities.len()).into());
+    }
+    let units: Vec<Box<dyn UnitTrait>> = save.units.drain(..).map(SavedUnit::restore).collect();
+    check_references(&save, &units, geo_and_cities)?;
+
+    // Phases are put back after AppState::new has started a fresh turn
+    let mut players = Vec::new();
+    let mut phases = Vec::new();
+    for saved in save.players {
+        let player_type = match saved.player_type {
+            SavedPlayerType::Player => PlayerType::Player,
+            SavedPlayerType::Ai(name) => PlayerType::Ai(strategy_named(&name).ok_or(format!("Unknown AI strategy {}", name))?),
+            SavedPlayerType::NotAssigned => PlayerType::NotAssigned,
+        };
+        let (r, g, b) = saved.colour;
+        let mut player = Player::new(&saved.name, player_type, Color::from_rgb(r, g, b));
+        player.treasury = saved.treasury;
+        player.production = saved.production;
+        player.cities = saved.cities;
+        player.capital = saved.capital;
+        player.eliminated = saved.eliminated;
+        players.push(player);
+        phases.push(saved.phase);
+    }
+
+    let mut app_state = AppState::new(players, geo_and_cities);
+    app_state.players.iter_mut().zip(phases).for_each(|(player, phase)| player.phase = phase);
+    app_state.cities = save.cities;
+    app_state.owners = app_state.players.iter().enumerate().flat_map(|(id, player)| player.cities.iter().map(move |&city| (city, id))).collect();
+    app_state.units = UnitRegistry::restore(units, save.next_unit);
+    app_state.turn = save.turn;
+    app_state.messages = save.messages;
+    app_state.combat_log = save.combat_log;
+    app_state.selected_city = save.selected_city;
+    app_state.selected_unit = save.selected_unit;
+    app_state.rng = save.rng;
+    app_state.recording = save.recording;
+    app_state.victory = save.victory;
+    app_state.game_over = save.game_over;
+    app_state.diplomacy = save.diplomacy;
+    app_state.bounds = save.bounds;
+    app_state.triggers = save.triggers;
+    app_state.script_flags = save.script_flags;
+    // The script itself comes with the setup it started from
+    let script = app_state.recording.setup.script.clone();
+    load_scripts(&mut app_state, script.as_deref())?;
+    app_state.territories.update(&app_state.players);
+    Ok((app_state, save.camera))
+}
+
+// Every player, city, unit and road node the save mentions has to exist, or the game would index past the end
+fn check_references(save: &SaveGame, units: &[Box<dyn UnitTrait>], geo_and_cities: &GeoWithPathAndCities) -> Result<(), String> {
+    let (players, cities) = (save.players.len(), save.cities.len());
+    let (nodes, edges) = (geo_and_cities.roads.nodes.len(), geo_and_cities.roads.edges.len());
+    let city = |id: CityId| {
+        if id < cities {
+            Ok(())
+        } else {
+            Err(format!("Save refers to city {} but the map has {}", id, cities))
+        }
+    };
+    let player = |id: PlayerId| {
+        if id < players {
+            Ok(())
+        } else {
+            Err(format!("Save refers to player {} but has {}", id, players))
+        }
+    };
+
+    // A city held twice would stay in both players' lists whichever one the game took as its owner
+    let mut held_by: Vec<Option<&str>> = vec![None; cities];
+    for saved in &save.players {
+        saved.cities.iter().chain(&saved.capital).try_for_each(|&id| city(id))?;
+        for &id in &saved.cities {
+            if let Some(other) = held_by[id].replace(&saved.name) {
+                return Err(format!("Save lists city {} twice, under {} and {}", id, other, saved.name));
+            }
+        }
+    }
+    for unit in units {
+        player(unit.owner())?;
+        let movement = unit.movement();
+        if movement.node >= nodes || movement.route.iter().any(|&edge| edge >= edges) {
+            return Err(format!("{} is off the road network", unit.get_name()));
+        }
+    }
+    save.selected_city.map_or(Ok(()), city)?;
+    if let Some(id) = save.selected_unit.filter(|&id| units.iter().all(|unit| unit.id() != id)) {
+        return Err(format!("Save selects unit {} which doesn't exist", id));
+    }
+    save.turn.current_player.map_or(Ok(()), player)?;
+    save.diplomacy.proposals.iter().try_for_each(|proposal| player(proposal.from).and(player(proposal.to)))
+}
+
+// Wherever the camera last was
+pub fn quicksave(app_state: &mut AppState) {
+    let camera = SavedCamera {
+        x: app_state.camera.target.x,
+        y: app_state.camera.target.y,
+        zoom: app_state.camera.zoom,
+    };
+    let message = match save_game(app_state, camera, QUICKSAVE) {
+        Ok(()) => format!("Saved to {}", QUICKSAVE),
+        Err(e) => format!("Failed to save: {}", e),
+    };
+    app_state.messages.add(app_state.turn.turn, message);
+}
+
+// Returns where the camera was when the game was saved
+pub fn quickload(app_state: &mut AppState, geo_and_cities: &GeoWithPathAndCities) -> Option<SavedCamera> {
+    match load_game(QUICKSAVE, geo_and_cities) {
+        Ok((mut loaded, camera)) => {
+            // Settings from the command line rather than the game
+            loaded.isochrone_bands = std::mem::take(&mut app_state.isochrone_bands);
+            *app_state = loaded;
+            app_state.messages.add(app_state.turn.turn, format!("Loaded {}", QUICKSAVE));
+            Some(camera)
+        }
+        Err(e) => {
+            app_state.messages.add(app_state.turn.turn, format!("Failed to load: {}", e));
+            None
+        }
+    }
+}
@@ -0,0 +1,409 @@
+use crate::app_state::AppState;
+use crate::game::army::{Army, ARMY_STRENGTH};
+use crate::game::city::CityId;
+use crate::game::diplomacy::Relation;
+use crate::game::player::PlayerId;
+use crate::game::setup::{GameSetup, PlayerSetup, MAX_PLAYERS, MIN_PLAYERS, PALETTE, UNASSIGNED};
+use crate::game::spy::Spy;
+use crate::game::victory::VictoryConditions;
+use crate::geo::crs::Bng;
+use crate::geo::data::{GeoWithPathAndCities, Location};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::{read_to_string, File};
+use std::io::BufReader;
+use std::path::Path;
+use std::rc::Rc;
+
+// Part of the map to play on, in BNG metres. Cities outside belong to nobody and can't be taken.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Bounds {
+    pub min_easting: f64,
+    pub min_northing: f64,
+    pub max_easting: f64,
+    pub max_northing: f64,
+}
+
+impl Bounds {
+    pub fn contains(&self, bng: Bng) -> bool {
+        (self.min_easting..=self.max_easting).contains(&bng.easting) && (self.min_northing..=self.max_northing).contains(&bng.northing)
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum UnitKind {
+    Army,
+    Spy,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ScenarioUnit {
+    pub kind: UnitKind,
+    // Starts on the road nearest this city
+    pub city: String,
+    // Armies only, otherwise the usual
+    #[serde(default)]
+    pub strength: Option<u32>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ScenarioPlayer {
+    pub name: String,
+    #[serde(default)]
+    pub human: bool,
+    // Index into PALETTE
+    pub colour: usize,
+    pub cities: Vec<String>,
+    // The first city if not given
+    #[serde(default)]
+    pub capital: Option<String>,
+    #[serde(default)]
+    pub treasury: Option<f64>,
+    #[serde(default)]
+    pub production: Option<f64>,
+    #[serde(default)]
+    pub units: Vec<ScenarioUnit>,
+}
+
+// How two players stand at the start, otherwise they're at war
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Treaty {
+    pub players: (String, String),
+    pub relation: Relation,
+}
+
+// Checked as each turn starts, players and cities by name
+#[derive(Clone, Serialize, Deserialize)]
+pub enum Condition {
+    Turn(u32),
+    CityOwned {
+        city: String,
+        player: String,
+    },
+    Eliminated(String),
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum Action {
+    Message(String),
+    Gold {
+        player: String,
+        amount: f64,
+    },
+    Army {
+        player: String,
+        city: String,
+        strength: u32,
+    },
+    Relation {
+        players: (String, String),
+        relation: Relation,
+    },
+}
+
+// Happens once, the first time its condition holds
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Trigger {
+    pub when: Condition,
+    pub then: Vec<Action>,
+    #[serde(default)]
+    pub fired: bool,
+}
+
+/// A hand made game, read from JSON. Players, their cities and units are set out in full
+/// rather than allocated, and triggers script what happens as it goes.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Scenario {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub bounds: Option<Bounds>,
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub victory: VictoryConditions,
+    pub players: Vec<ScenarioPlayer>,
+    #[serde(default)]
+    pub relations: Vec<Treaty>,
+    #[serde(default)]
+    pub triggers: Vec<Trigger>,
+    // Rhai file, relative to the scenario
+    #[serde(default)]
+    pub script: Option<String>,
+    // Read in from the script file when the scenario is loaded, and handed on to the setup
+    #[serde(skip)]
+    pub script_source: Option<String>,
+}
+
+// The biggest city of that name, ignoring case
+pub fn city_named(locations: &[Rc<Location>], name: &str) -> Option<CityId> {
+    locations
+        .iter()
+        .enumerate()
+        .filter(|(_, location)| location.name.eq_ignore_ascii_case(name))
+        .max_by_key(|(_, location)| location.population)
+        .map(|(city, _)| city)
+}
+
+fn player_named(app_state: &AppState, name: &str) -> Option<PlayerId> {
+    app_state.players.iter().position(|player| player.name == name)
+}
+
+impl Scenario {
+    // Everything named has to exist, so the game can be set up without further checks
+    fn validate(&self, geo_and_cities: &GeoWithPathAndCities) -> Result<(), String> {
+        let locations = &geo_and_cities.cities;
+        if !(MIN_PLAYERS..=MAX_PLAYERS).contains(&self.players.len()) {
+            return Err(format!("Scenario needs {} to {} players", MIN_PLAYERS, MAX_PLAYERS));
+        }
+        // Players are found by name and told apart by colour, and the unowned cities already have a name
+        for (index, scenario_player) in self.players.iter().enumerate() {
+            if scenario_player.name == UNASSIGNED {
+                return Err(format!("{} is kept for cities nobody holds", UNASSIGNED));
+            }
+            if let Some(other) = self.players[..index].iter().find(|other| other.name == scenario_player.name) {
+                return Err(format!("There are two players called {}", other.name));
+            }
+            if let Some(other) = self.players[..index].iter().find(|other| other.colour == scenario_player.colour) {
+                return Err(format!("{} and {} have the same colour", other.name, scenario_player.name));
+            }
+        }
+        let player = |name: &str| {
+            if self.players.iter().any(|player| player.name == name) {
+                Ok(())
+            } else {
+                Err(format!("No player called {}", name))
+            }
+        };
+        let city = |name: &str| {
+            let city = city_named(locations, name).ok_or(format!("No city called {}", name))?;
+            let at = Bng::from_scaled(locations[city].x, locations[city].y);
+            if self.bounds.is_none_or(|bounds| bounds.contains(at)) {
+                Ok(())
+            } else {
+                Err(format!("{} is outside the scenario's bounds", name))
+            }
+        };
+        // Units go on the road nearest the city, which has to be inside the bounds too
+        let placement = |name: &str| {
+            city(name)?;
+            let node = city_named(locations, name)
+                .and_then(|city| geo_and_cities.roads.nearest_node(locations[city].world()))
+                .ok_or(format!("No road near {}", name))?;
+            if self.bounds.is_none_or(|bounds| bounds.contains(geo_and_cities.roads.nodes[node].bng())) {
+                Ok(())
+            } else {
+                Err(format!("The road nearest {} is outside the scenario's bounds", name))
+            }
+        };
+
+        for scenario_player in &self.players {
+            if scenario_player.colour >= PALETTE.len() {
+                return Err(format!("{} has colour {}, there are only {}", scenario_player.name, scenario_player.colour, PALETTE.len()));
+            }
+            if scenario_player.cities.is_empty() {
+                return Err(format!("{} has no cities", scenario_player.name));
+            }
+            scenario_player.cities.iter().try_for_each(|name| city(name))?;
+            scenario_player.units.iter().try_for_each(|unit| placement(&unit.city))?;
+            if let Some(capital) = &scenario_player.capital {
+                if !scenario_player.cities.contains(capital) {
+                    return Err(format!("{}'s capital {} isn't one of its cities", scenario_player.name, capital));
+                }
+            }
+        }
+        for treaty in &self.relations {
+            player(&treaty.players.0)?;
+            player(&treaty.players.1)?;
+        }
+        for trigger in &self.triggers {
+            match &trigger.when {
+                Condition::Turn(_) => {}
+                Condition::CityOwned {
+                    city: name,
+                    player: owner,
+                } => {
+                    city(name)?;
+                    player(owner)?;
+                }
+                Condition::Eliminated(name) => player(name)?,
+            }
+            for action in &trigger.then {
+                match action {
+                    Action::Message(_) => {}
+                    Action::Gold {
+                        player: name,
+                        ..
+                    } => player(name)?,
+                    Action::Army {
+                        player: name,
+                        city: at,
+                        ..
+                    } => {
+                        player(name)?;
+                        placement(at)?;
+                    }
+                    Action::Relation {
+                        players,
+                        ..
+                    } => {
+                        player(&players.0)?;
+                        player(&players.1)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // What the game is started from, so the scenario goes to joining players and into replays
+    pub fn setup(&self) -> GameSetup {
+        GameSetup {
+            players: self
+                .players
+                .iter()
+                .map(|player| PlayerSetup {
+                    name: player.name.clone(),
+                    human: player.human,
+                    colour: player.colour,
+                })
+                .collect(),
+            seed: self.seed,
+            victory: self.victory.clone(),
+            // The setup holds the only copy of the script
+            scenario: Some(Scenario {
+                script_source: None,
+                ..self.clone()
+            }),
+            script: self.script_source.clone(),
+        }
+    }
+}
+
+pub fn load_scenario(path: &str, geo_and_cities: &GeoWithPathAndCities) -> Result<Scenario, Box<dyn Error>> {
+    let mut scenario: Scenario = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+    scenario.validate(geo_and_cities)?;
+    if let Some(script) = &scenario.script {
+        let script_path = Path::new(path).parent().unwrap_or(Path::new("")).join(script);
+        let source = read_to_string(&script_path).map_err(|e| format!("Can't read {}: {}", script_path.display(), e))?;
+        scenario.script_source = Some(source);
+    }
+    Ok(scenario)
+}
+
+// Put a unit on the road nearest a city
+pub fn place_unit(app_state: &mut AppState, geo_and_cities: &GeoWithPathAndCities, owner: PlayerId, city: CityId, kind: &UnitKind, strength: Option<u32>) {
+    let location = &geo_and_cities.cities[city];
+    let node = match geo_and_cities.roads.nearest_node(location.world()) {
+        Some(node) => node,
+        None => return,
+    };
+    match kind {
+        UnitKind::Army => {
+            let name = format!("{} Army", location.name);
+            app_state.units.add(|id| Army::new(id, name, owner, strength.unwrap_or(ARMY_STRENGTH), node));
+        }
+        UnitKind::Spy => {
+            app_state.units.add(|id| Spy::new(id, format!("Agent {:03}", id + 1), owner, node));
+        }
+    }
+}
+
+// Hand out cities and units as the scenario says, players are already in place from its setup
+pub fn apply_scenario(app_state: &mut AppState, geo_and_cities: &GeoWithPathAndCities, scenario: &Scenario) {
+    let locations = &geo_and_cities.cities;
+    app_state.bounds = scenario.bounds;
+    for (city, location) in locations.iter().enumerate() {
+        if scenario.bounds.is_none_or(|bounds| bounds.contains(Bng::from_scaled(location.x, location.y))) {
+            app_state.players[0].add_city(city);
+            app_state.owners.insert(city, 0);
+        }
+    }
+
+    for (index, scenario_player) in scenario.players.iter().enumerate() {
+        let player = index + 1;
+        for city in scenario_player.cities.iter().filter_map(|name| city_named(locations, name)) {
+            app_state.transfer_city(city, player);
+        }
+        let capital = scenario_player.capital.as_ref().unwrap_or(&scenario_player.cities[0]);
+        app_state.players[player].capital = city_named(locations, capital);
+        if let Some(treasury) = scenario_player.treasury {
+            app_state.players[player].treasury = treasury;
+        }
+        if let Some(production) = scenario_player.production {
+            app_state.players[player].production = production;
+        }
+        for unit in &scenario_player.units {
+            if let Some(city) = city_named(locations, &unit.city) {
+                place_unit(app_state, geo_and_cities, player, city, &unit.kind, unit.strength);
+            }
+        }
+    }
+
+    for treaty in &scenario.relations {
+        if let (Some(a), Some(b)) = (player_named(app_state, &treaty.players.0), player_named(app_state, &treaty.players.1)) {
+            app_state.diplomacy.set_relation(a, b, treaty.relation);
+        }
+    }
+    app_state.triggers = scenario.triggers.clone();
+    if !scenario.description.is_empty() {
+        app_state.messages.add(app_state.turn.turn, scenario.description.clone());
+    }
+}
+
+fn holds(app_state: &AppState, condition: &Condition) -> bool {
+    match condition {
+        Condition::Turn(turn) => app_state.turn.turn >= *turn,
+        Condition::CityOwned {
+            city,
+            player,
+        } => city_named(&app_state.locations, city).and_then(|city| app_state.owner_of(city)) == player_named(app_state, player),
+        Condition::Eliminated(player) => player_named(app_state, player).is_some_and(|player| app_state.players[player].eliminated),
+    }
+}
+
+fn act(app_state: &mut AppState, geo_and_cities: &GeoWithPathAndCities, action: &Action) {
+    let turn = app_state.turn.turn;
+    match action {
+        Action::Message(message) => app_state.messages.add(turn, message.clone()),
+        Action::Gold {
+            player,
+            amount,
+        } => {
+            if let Some(player) = player_named(app_state, player) {
+                app_state.players[player].treasury += amount;
+            }
+        }
+        Action::Army {
+            player,
+            city,
+            strength,
+        } => {
+            if let (Some(player), Some(city)) = (player_named(app_state, player), city_named(&geo_and_cities.cities, city)) {
+                place_unit(app_state, geo_and_cities, player, city, &UnitKind::Army, Some(*strength));
+            }
+        }
+        Action::Relation {
+            players,
+            relation,
+        } => {
+            if let (Some(a), Some(b)) = (player_named(app_state, &players.0), player_named(app_state, &players.1)) {
+                app_state.diplomacy.set_relation(a, b, *relation);
+            }
+        }
+    }
+}
+
+// As each turn starts, fire any triggers whose time has come
+pub fn run_triggers(app_state: &mut AppState, geo_and_cities: &GeoWithPathAndCities) {
+    for index in 0..app_state.triggers.len() {
+        if app_state.triggers[index].fired || !holds(app_state, &app_state.triggers[index].when) {
+            continue;
+        }
+        app_state.triggers[index].fired = true;
+        for action in app_state.triggers[index].then.clone() {
+            act(app_state, geo_and_cities, &action);
+        }
+    }
+}
@@ -0,0 +1,381 @@
+use crate::app_state::AppState;
+use crate::game::army::Army;
+use crate::game::city::CityId;
+use crate::game::diplomacy::Relation;
+use crate::game::player::{PlayerId, PlayerType};
+use crate::game::scenario::{city_named, place_unit, UnitKind};
+use crate::game::units::UnitId;
+use crate::geo::data::{GeoWithPathAndCities, Location};
+use rhai::{Array, CallFnOptions, Dynamic, Engine, EvalAltResult, Map, Position, Scope, AST, FLOAT, INT};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::rc::Rc;
+
+// A runaway script is stopped after this many operations in one hook
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+// Points in the game a script can act on, each calls the script function of that name if there is one
+pub enum Hook {
+    TurnStart,
+    TurnEnd,
+    CityCaptured {
+        city: CityId,
+        from: PlayerId,
+        to: PlayerId,
+    },
+    UnitMoved {
+        unit: UnitId,
+    },
+}
+
+impl Hook {
+    fn function(&self) -> &str {
+        match self {
+            Hook::TurnStart => "on_turn_start",
+            Hook::TurnEnd => "on_turn_end",
+            Hook::CityCaptured {
+                ..
+            } => "on_city_captured",
+            Hook::UnitMoved {
+                ..
+            } => "on_unit_moved",
+        }
+    }
+
+    // After the game itself, which is always the first argument
+    fn arguments(&self) -> Vec<Dynamic> {
+        match self {
+            Hook::TurnStart | Hook::TurnEnd => Vec::new(),
+            Hook::CityCaptured {
+                city,
+                from,
+                to,
+            } => vec![Dynamic::from(*city as INT), Dynamic::from(*from as INT), Dynamic::from(*to as INT)],
+            Hook::UnitMoved {
+                unit,
+            } => vec![Dynamic::from(*unit as INT)],
+        }
+    }
+}
+
+// What a script asks for, carried out once it returns so scripts never hold the game itself
+enum ScriptAction {
+    Message(String),
+    Gold(INT, FLOAT),
+    Army(INT, INT, INT),
+    TransferCity(INT, INT),
+    Strength(INT, INT),
+    Relation(INT, INT, String),
+    Flag(String, INT),
+}
+
+// One of the game's lists as a script sees it, indexed and iterated without copying the whole list
+#[derive(Clone)]
+struct ScriptList(Rc<Array>);
+
+impl IntoIterator for ScriptList {
+    type Item = Dynamic;
+    type IntoIter = ScriptListIter;
+
+    fn into_iter(self) -> ScriptListIter {
+        ScriptListIter {
+            list: self.0,
+            next: 0,
+        }
+    }
+}
+
+struct ScriptListIter {
+    list: Rc<Array>,
+    next: usize,
+}
+
+impl Iterator for ScriptListIter {
+    type Item = Dynamic;
+
+    fn next(&mut self) -> Option<Dynamic> {
+        let item = self.list.get(self.next).cloned();
+        self.next += 1;
+        item
+    }
+}
+
+// The game copied out for scripts, kept across hooks until one of them changes something
+struct Snapshot {
+    turn: INT,
+    players: ScriptList,
+    cities: ScriptList,
+    units: ScriptList,
+    flags: Map,
+}
+
+impl Snapshot {
+    fn new(app_state: &AppState) -> Snapshot {
+        let map = |entries: Vec<(&str, Dynamic)>| -> Dynamic { Dynamic::from_map(entries.into_iter().map(|(key, value)| (key.into(), value)).collect()) };
+        let players = app_state
+            .players
+            .iter()
+            .enumerate()
+            .map(|(id, player)| {
+                map(vec![
+                    ("id", Dynamic::from(id as INT)),
+                    ("name", player.name.clone().into()),
+                    ("human", matches!(player.player_type, PlayerType::Player).into()),
+                    ("treasury", Dynamic::from(player.treasury as FLOAT)),
+                    ("production", Dynamic::from(player.production as FLOAT)),
+                    ("cities", Dynamic::from_array(player.cities.iter().map(|&city| Dynamic::from(city as INT)).collect())),
+                    ("capital", player.capital.map_or(Dynamic::UNIT, |city| Dynamic::from(city as INT))),
+                    ("eliminated", player.eliminated.into()),
+                ])
+            })
+            .collect();
+        let cities = app_state
+            .cities
+            .iter()
+            .enumerate()
+            .map(|(id, city)| {
+                let location = app_state.location(id);
+                map(vec![
+                    ("id", Dynamic::from(id as INT)),
+                    ("name", location.name.clone().into()),
+                    ("population", Dynamic::from(city.economy.population as FLOAT)),
+                    ("owner", app_state.owner_of(id).map_or(Dynamic::UNIT, |owner| Dynamic::from(owner as INT))),
+                ])
+            })
+            .collect();
+        let units = app_state
+            .units
+            .iter()
+            .map(|unit| {
+                let strength = unit.as_any().downcast_ref::<Army>().map_or(Dynamic::UNIT, |army| Dynamic::from(army.strength as INT));
+                map(vec![
+                    ("id", Dynamic::from(unit.id() as INT)),
+                    ("name", unit.get_name().to_string().into()),
+                    ("owner", Dynamic::from(unit.owner() as INT)),
+                    ("strength", strength),
+                    ("node", Dynamic::from(unit.movement().node as INT)),
+                    ("moving", unit.movement().is_moving().into()),
+                ])
+            })
+            .collect();
+        Snapshot {
+            turn: app_state.turn.turn as INT,
+            players: ScriptList(Rc::new(players)),
+            cities: ScriptList(Rc::new(cities)),
+            units: ScriptList(Rc::new(units)),
+            flags: app_state.script_flags.iter().map(|(key, &value)| (key.into(), Dynamic::from(value))).collect(),
+        }
+    }
+}
+
+/// The game as a script sees it: read only lists of players, cities and units, and
+/// methods that queue changes. Ids are the same as the game's own.
+#[derive(Clone)]
+struct ScriptGame {
+    snapshot: Rc<Snapshot>,
+    actions: Rc<RefCell<Vec<ScriptAction>>>,
+}
+
+impl ScriptGame {
+    fn queue(&mut self, action: ScriptAction) {
+        self.actions.borrow_mut().push(action);
+    }
+}
+
+fn register(engine: &mut Engine, locations: &[Rc<Location>]) {
+    // A city's id by name, or -1
+    let locations = locations.to_vec();
+    engine.register_fn("city_named", move |name: &str| city_named(&locations, name).map_or(-1, |city| city as INT));
+    engine
+        .register_type_with_name::<ScriptList>("List")
+        .register_indexer_get(|list: &mut ScriptList, index: INT| -> Result<Dynamic, Box<EvalAltResult>> {
+            let item = usize::try_from(index).ok().and_then(|index| list.0.get(index));
+            item.cloned().ok_or_else(|| EvalAltResult::ErrorArrayBounds(list.0.len(), index, Position::NONE).into())
+        })
+        .register_fn("len", |list: &mut ScriptList| list.0.len() as INT)
+        .register_get("len", |list: &mut ScriptList| list.0.len() as INT)
+        .register_iterator::<ScriptList>();
+    engine
+        .register_type_with_name::<ScriptGame>("Game")
+        .register_get("turn", |game: &mut ScriptGame| game.snapshot.turn)
+        .register_get("players", |game: &mut ScriptGame| game.snapshot.players.clone())
+        .register_get("cities", |game: &mut ScriptGame| game.snapshot.cities.clone())
+        .register_get("units", |game: &mut ScriptGame| game.snapshot.units.clone())
+        .register_fn("flag", |game: &mut ScriptGame, name: &str| game.snapshot.flags.get(name).and_then(|value| value.as_int().ok()).unwrap_or(0))
+        .register_fn("set_flag", |game: &mut ScriptGame, name: &str, value: INT| game.queue(ScriptAction::Flag(name.to_string(), value)))
+        .register_fn("message", |game: &mut ScriptGame, text: &str| game.queue(ScriptAction::Message(text.to_string())))
+        .register_fn("give_gold", |game: &mut ScriptGame, player: INT, amount: FLOAT| game.queue(ScriptAction::Gold(player, amount)))
+        .register_fn("give_gold", |game: &mut ScriptGame, player: INT, amount: INT| game.queue(ScriptAction::Gold(player, amount as FLOAT)))
+        .register_fn("raise_army", |game: &mut ScriptGame, player: INT, city: INT, strength: INT| game.queue(ScriptAction::Army(player, city, strength)))
+        .register_fn("transfer_city", |game: &mut ScriptGame, city: INT, player: INT| game.queue(ScriptAction::TransferCity(city, player)))
+        .register_fn("set_strength", |game: &mut ScriptGame, unit: INT, strength: INT| game.queue(ScriptAction::Strength(unit, strength)))
+        .register_fn("set_relation", |game: &mut ScriptGame, a: INT, b: INT, relation: &str| game.queue(ScriptAction::Relation(a, b, relation.to_string())));
+}
+
+/// Rhai scripts for scenarios and trying out rules. A script defines any of on_turn_start(game),
+/// on_turn_end(game), on_city_captured(game, city, from, to) and on_unit_moved(game, unit).
+pub struct Scripts {
+    engine: Engine,
+    ast: AST,
+    // Names of the hooks the script defines
+    hooks: Vec<String>,
+}
+
+impl Scripts {
+    pub fn new(source: &str, locations: &[Rc<Location>]) -> Result<Scripts, Box<dyn Error>> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        register(&mut engine, locations);
+        let ast = engine.compile(source)?;
+        let hooks = ast.iter_functions().map(|function| function.name.to_string()).collect();
+        Ok(Scripts {
+            engine,
+            ast,
+            hooks,
+        })
+    }
+
+    fn call(&self, hook: &Hook, game: ScriptGame) -> Result<(), String> {
+        let mut arguments = vec![Dynamic::from(game)];
+        arguments.extend(hook.arguments());
+        let options = CallFnOptions::new().eval_ast(false);
+        self.engine.call_fn_with_options::<Dynamic>(options, &mut Scope::new(), &self.ast, hook.function(), arguments).map(|_| ()).map_err(|e| e.to_string())
+    }
+}
+
+// Let the script have its say, then carry out whatever it asked for
+pub fn run_hook(app_state: &mut AppState, geo_and_cities: &GeoWithPathAndCities, hook: Hook) {
+    run_hooks(app_state, geo_and_cities, vec![hook]);
+}
+
+// Several hooks one after another, the game is only copied out again once a script has changed it
+pub fn run_hooks(app_state: &mut AppState, geo_and_cities: &GeoWithPathAndCities, hooks: Vec<Hook>) {
+    let mut snapshot: Option<Rc<Snapshot>> = None;
+    for hook in hooks {
+        let result = match &app_state.scripts {
+            Some(scripts) if scripts.hooks.iter().any(|name| name == hook.function()) => {
+                let game = ScriptGame {
+                    snapshot: snapshot.get_or_insert_with(|| Rc::new(Snapshot::new(app_state))).clone(),
+                    actions: Rc::default(),
+                };
+                let actions = game.actions.clone();
+                scripts.call(&hook, game).map(|_| actions.take())
+            }
+            _ => continue,
+        };
+        match result {
+            Ok(actions) => {
+                if !actions.is_empty() {
+                    snapshot = None;
+                }
+                actions.into_iter().for_each(|action| act(app_state, geo_and_cities, action));
+            }
+            Err(e) => app_state.messages.add(app_state.turn.turn, format!("Script error in {}: {}", hook.function(), e)),
+        }
+    }
+}
+
+fn act(app_state: &mut AppState, geo_and_cities: &GeoWithPathAndCities, action: ScriptAction) {
+    let turn = app_state.turn.turn;
+    let player = |id: INT| usize::try_from(id).ok().filter(|&id| id < app_state.players.len());
+    let city = |id: INT| usize::try_from(id).ok().filter(|&id| id < app_state.cities.len());
+    match action {
+        ScriptAction::Message(message) => app_state.messages.add(turn, message),
+        ScriptAction::Gold(id, amount) => {
+            if let Some(id) = player(id) {
+                app_state.players[id].treasury += amount;
+            }
+        }
+        ScriptAction::Army(owner, at, strength) => {
+            if let (Some(owner), Some(at)) = (player(owner), city(at)) {
+                let strength = u32::try_from(strength.max(1)).unwrap_or(u32::MAX);
+                place_unit(app_state, geo_and_cities, owner, at, &UnitKind::Army, Some(strength));
+            }
+        }
+        ScriptAction::TransferCity(id, to) => {
+            if let (Some(id), Some(to)) = (city(id), player(to)) {
+                app_state.transfer_city(id, to);
+            }
+        }
+        ScriptAction::Strength(unit, strength) => {
+            let unit = unit as UnitId;
+            if let Some(army) = app_state.units.get_as_mut::<Army>(unit) {
+                army.strength = u32::try_from(strength.max(0)).unwrap_or(u32::MAX);
+                if army.strength == 0 {
+                    app_state.units.remove(unit);
+                }
+            }
+        }
+        ScriptAction::Relation(a, b, relation) => {
+            let relation = match relation.to_lowercase().as_str() {
+                "war" => Relation::War,
+                "peace" => Relation::Peace,
+                "alliance" => Relation::Alliance,
+                _ => return,
+            };
+            if let (Some(a), Some(b)) = (player(a), player(b)) {
+                app_state.diplomacy.set_relation(a, b, relation);
+            }
+        }
+        ScriptAction::Flag(name, value) => {
+            app_state.script_flags.insert(name, value);
+        }
+    }
+}
+
+// Flags a script keeps between hooks, saved with the game
+pub type ScriptFlags = BTreeMap<String, INT>;
+
+// Compiled once, as the game starts or is loaded, and a script that won't compile stops it
+pub fn load_scripts(app_state: &mut AppState, source: Option<&str>) -> Result<(), String> {
+    app_state.scripts = source.map(|source| Scripts::new(source, &app_state.locations)).transpose().map_err(|e| format!("Script failed to compile: {}", e))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::setup::{new_game, GameSetup};
+    use crate::geo::data::test_geo;
+
+    fn start(script: Option<&str>) -> Result<AppState, Box<dyn Error>> {
+        let setup = GameSetup {
+            seed: Some(3),
+            script: script.map(str::to_string),
+            ..GameSetup::default()
+        };
+        new_game(&setup, &test_geo())
+    }
+
+    #[test]
+    fn turn_start_hooks_see_the_game_and_change_it() {
+        let script = r#"
+            fn on_turn_start(game) {
+                let player = game.players[1];
+                let held = 0;
+                for city in game.cities {
+                    if city.owner == player.id { held += 1; }
+                }
+                game.set_flag("held", held);
+                game.give_gold(player.id, 10);
+            }
+        "#;
+        let plain = start(None).unwrap();
+        let app_state = start(Some(script)).unwrap();
+        assert_eq!(app_state.players[1].treasury - plain.players[1].treasury, 10.0);
+        assert_eq!(app_state.script_flags["held"], app_state.players[1].cities.len() as INT);
+    }
+
+    #[test]
+    fn reading_past_the_end_of_a_list_is_a_script_error() {
+        let app_state = start(Some("fn on_turn_start(game) { game.set_flag(\"name\", game.players[99].id); }")).unwrap();
+        assert!(app_state.script_flags.is_empty());
+        assert!(app_state.messages.entries.iter().any(|message| message.text.starts_with("Script error in on_turn_start")));
+    }
+
+    #[test]
+    fn a_script_that_wont_compile_stops_the_game_starting() {
+        assert!(start(Some("fn on_turn_start(game) {")).is_err());
+    }
+}
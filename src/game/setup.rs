@@ -0,0 +1,247 @@
+use crate::app_state::AppState;
+use crate::game::ai::Heuristic;
+use crate::game::city::CityId;
+use crate::game::player::{Player, PlayerType};
+use crate::game::random::GameRng;
+use crate::game::replay::{record_turn, Recording};
+use crate::game::scenario::{apply_scenario, run_triggers, Scenario};
+use crate::game::script::{load_scripts, run_hook, Hook};
+use crate::game::victory::VictoryConditions;
+use crate::geo::crs::Bng;
+use crate::geo::data::{GeoWithPathAndCities, Location};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use skia_safe::Color;
+use std::error::Error;
+use std::rc::Rc;
+
+pub const MIN_PLAYERS: usize = 2;
+pub const MAX_PLAYERS: usize = 6;
+
+// Player 0, who holds the cities nobody else does
+pub const UNASSIGNED: &str = "Unassigned";
+
+pub const PALETTE: [(u8, u8, u8); 8] =
+    [(200, 30, 30), (30, 60, 200), (30, 150, 50), (200, 140, 0), (130, 40, 170), (0, 150, 160), (200, 60, 150), (90, 90, 90)];
+
+// Metres between starting cities, halved until everyone fits
+const START_SPACING: f64 = 150_000.0;
+
+// Only the biggest cities are considered for a start, this many for each player
+const CANDIDATES_PER_PLAYER: usize = 4;
+
+// Random draws of start cities, the fairest is kept
+const ATTEMPTS: usize = 500;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PlayerSetup {
+    pub name: String,
+    pub human: bool,
+    // Index into PALETTE
+    pub colour: usize,
+}
+
+impl PlayerSetup {
+    pub fn colour(&self) -> Color {
+        let (r, g, b) = PALETTE[self.colour];
+        Color::from_rgb(r, g, b)
+    }
+}
+
+// The choices made before a game starts
+#[derive(Clone, Serialize, Deserialize)]
+pub struct GameSetup {
+    pub players: Vec<PlayerSetup>,
+    // None for a different game each time
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub victory: VictoryConditions,
+    // Cities, units and triggers set out by hand, instead of allocated
+    #[serde(default)]
+    pub scenario: Option<Scenario>,
+    // Rhai source run alongside the game
+    #[serde(default)]
+    pub script: Option<String>,
+}
+
+impl Default for GameSetup {
+    fn default() -> GameSetup {
+        let mut setup = GameSetup {
+            players: Vec::new(),
+            seed: None,
+            victory: VictoryConditions::default(),
+            scenario: None,
+            script: None,
+        };
+        setup.add_player();
+        setup.add_player();
+        setup.players[0].human = true;
+        setup
+    }
+}
+
+impl GameSetup {
+    // --players <n>, --seed <n> and --turns <n>
+    pub fn from_args(args: &[String]) -> GameSetup {
+        let value = |flag: &str| args.iter().position(|arg| arg == flag).and_then(|i| args.get(i + 1)).and_then(|value| value.parse().ok());
+        let mut setup = GameSetup::default();
+        if let Some(count) = value("--players") {
+            while setup.players.len() < (count as usize).min(MAX_PLAYERS) {
+                setup.add_player();
+            }
+            while setup.players.len() > (count as usize).max(MIN_PLAYERS) {
+                setup.remove_player();
+            }
+        }
+        setup.seed = value("--seed");
+        if let Some(turns) = value("--turns") {
+            setup.victory.turn_limit = Some(turns as u32);
+        }
+        setup
+    }
+
+    // New players are computer controlled, in the first free colour
+    pub fn add_player(&mut self) {
+        if self.players.len() >= MAX_PLAYERS {
+            return;
+        }
+        let colour = (0..PALETTE.len()).find(|&c| self.players.iter().all(|p| p.colour != c)).unwrap_or_default();
+        self.players.push(PlayerSetup {
+            name: format!("Player {}", self.players.len() + 1),
+            human: false,
+            colour,
+        });
+    }
+
+    pub fn remove_player(&mut self) {
+        if self.players.len() > MIN_PLAYERS {
+            self.players.pop();
+        }
+    }
+
+    // Typed in a digit at a time, digits that would overflow are ignored
+    pub fn type_seed_digit(&mut self, digit: u32) {
+        let seed = self.seed.unwrap_or(0).checked_mul(10).and_then(|seed| seed.checked_add(digit as u64));
+        if let Some(seed) = seed {
+            self.seed = Some(seed);
+        }
+    }
+
+    // Back to a random seed once every digit has gone
+    pub fn erase_seed_digit(&mut self) {
+        self.seed = self.seed.map(|seed| seed / 10).filter(|&seed| seed > 0);
+    }
+
+    pub fn toggle_human(&mut self, index: usize) {
+        if let Some(player) = self.players.get_mut(index) {
+            player.human = !player.human;
+        }
+    }
+
+    // Next colour along that nobody else has
+    pub fn next_colour(&mut self, index: usize) {
+        let current = self.players[index].colour;
+        let next = (1..PALETTE.len()).map(|step| (current + step) % PALETTE.len()).find(|&c| self.players.iter().all(|p| p.colour != c));
+        if let Some(next) = next {
+            self.players[index].colour = next;
+        }
+    }
+
+    fn to_players(&self) -> Vec<Player> {
+        let mut players = vec![Player::new(UNASSIGNED, PlayerType::NotAssigned, Color::BLACK)];
+        players.extend(self.players.iter().map(|setup| {
+            let player_type = if setup.human {
+                PlayerType::Player
+            } else {
+                PlayerType::Ai(Box::new(Heuristic))
+            };
+            Player::new(&setup.name, player_type, setup.colour())
+        }));
+        players
+    }
+}
+
+/// Start cities for count players: similar in size and at least spacing apart. Draws from the
+/// biggest cities many times over and keeps the draw with the closest populations.
+/// Fails only if there are fewer cities than players.
+pub fn allocate_start_cities(locations: &[Rc<Location>], count: usize, spacing: f64, rng: &mut impl Rng) -> Result<Vec<CityId>, String> {
+    if locations.len() < count {
+        return Err(format!("{} players need a city each but the map has {}", count, locations.len()));
+    }
+    let mut candidates: Vec<CityId> = (0..locations.len()).collect();
+    candidates.sort_by_key(|&city| std::cmp::Reverse(locations[city].population));
+    candidates.truncate(count * CANDIDATES_PER_PLAYER);
+    let at = |city: CityId| Bng::from_scaled(locations[city].x, locations[city].y);
+    let population = |city: CityId| locations[city].population.max(1) as f64;
+
+    let mut spacing = spacing;
+    loop {
+        let mut best: Option<(f64, Vec<CityId>)> = None;
+        for _ in 0..ATTEMPTS {
+            candidates.shuffle(rng);
+            let mut picked: Vec<CityId> = Vec::new();
+            for &city in &candidates {
+                if picked.len() < count && picked.iter().all(|&other| at(other).distance(at(city)) >= spacing) {
+                    picked.push(city);
+                }
+            }
+            if picked.len() < count {
+                continue;
+            }
+            let smallest = picked.iter().map(|&city| population(city)).fold(f64::INFINITY, f64::min);
+            let biggest = picked.iter().map(|&city| population(city)).fold(0.0, f64::max);
+            let fairness = smallest / biggest;
+            if best.as_ref().is_none_or(|(score, _)| fairness > *score) {
+                best = Some((fairness, picked));
+            }
+        }
+        if let Some((_, picked)) = best {
+            return Ok(picked);
+        }
+        // Cities all but on top of each other, any of the biggest will do
+        if spacing < 1000.0 {
+            return Ok(candidates.into_iter().take(count).collect());
+        }
+        spacing /= 2.0;
+    }
+}
+
+// Every city starts unassigned, then each player is given one, unless a scenario says otherwise
+pub fn new_game(setup: &GameSetup, geo_and_cities: &GeoWithPathAndCities) -> Result<AppState, Box<dyn Error>> {
+    let seed = setup.seed.unwrap_or_else(|| rand::rng().random());
+    let mut rng = GameRng::new(seed);
+
+    let mut app_state = AppState::new(setup.to_players(), geo_and_cities);
+    match &setup.scenario {
+        Some(scenario) => apply_scenario(&mut app_state, geo_and_cities, scenario),
+        None => {
+            app_state.assign_all_cities(0);
+            let starts = allocate_start_cities(&geo_and_cities.cities, setup.players.len(), START_SPACING, &mut rng)?;
+            for (player, city) in starts.into_iter().enumerate() {
+                app_state.transfer_city(city, player + 1);
+                app_state.players[player + 1].capital = Some(city);
+            }
+        }
+    }
+    app_state.victory = setup.victory.clone();
+    app_state.events.clear();
+    app_state.territories.update(&app_state.players);
+
+    let human = app_state.players.iter().position(|player| matches!(player.player_type, PlayerType::Player));
+    app_state.selected_city = human.and_then(|player| app_state.players[player].cities.first().copied());
+    app_state.rng = rng;
+    app_state.recording = Recording {
+        setup: GameSetup {
+            seed: Some(seed),
+            ..setup.clone()
+        },
+        ..Recording::default()
+    };
+    app_state.messages.add(app_state.turn.turn, format!("New game, seed {}", seed));
+    load_scripts(&mut app_state, setup.script.as_deref())?;
+    run_triggers(&mut app_state, geo_and_cities);
+    run_hook(&mut app_state, geo_and_cities, Hook::TurnStart);
+    record_turn(&mut app_state);
+    Ok(app_state)
+}
@@ -1,11 +1,185 @@
-use crate::game::units::UnitTrait;
+use crate::app_state::AppState;
+use crate::game::army::Army;
+use crate::game::city::CityId;
+use crate::game::movement::Movement;
+use crate::game::player::PlayerId;
+use crate::game::save::SavedUnit;
+use crate::game::units::{as_any, Marker, UnitContext, UnitId, UnitTrait};
+use crate::geo::crs::{Bng, World};
+use crate::geo::data::GeoWithPathAndCities;
+use crate::geo::roads::NodeId;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use skia_safe::Point;
 
+// Spies travel light, two hours of driving a turn
+pub const SPY_MOVEMENT_POINTS: f64 = 2.0 * 60.0 * 60.0;
+pub const SPY_COST: f64 = 10.0;
+const SPY_UPKEEP: f64 = 1.0;
+
+// How far a spy can see, km
+const SPY_VISION: f32 = 20.0;
+
+// A spy this close (metres) to a city can work in it
+const CITY_DISTANCE: f64 = 3000.0;
+
+const SABOTAGE_CHANCE: f64 = 0.6;
+const SABOTAGE_TURNS: u32 = 3;
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Spy {
+    pub id: UnitId,
     pub name: String,
+    pub owner: PlayerId,
+    pub movement: Movement,
+    // Sabotage is once a turn
+    #[serde(default)]
+    pub acted: bool,
 }
 
 impl UnitTrait for Spy {
     fn get_name(&self) -> &str {
         self.name.as_str()
     }
+
+    fn id(&self) -> UnitId {
+        self.id
+    }
+
+    fn owner(&self) -> PlayerId {
+        self.owner
+    }
+
+    fn movement(&self) -> &Movement {
+        &self.movement
+    }
+
+    fn movement_mut(&mut self) -> &mut Movement {
+        &mut self.movement
+    }
+
+    fn upkeep(&self) -> f64 {
+        SPY_UPKEEP
+    }
+
+    fn vision_radius(&self) -> f32 {
+        SPY_VISION
+    }
+
+    fn marker(&self) -> Marker {
+        Marker::Diamond
+    }
+
+    fn is_covert(&self) -> bool {
+        true
+    }
+
+    fn gathers_intel(&self) -> bool {
+        true
+    }
+
+    fn on_turn_start(&mut self, _context: &mut UnitContext) {
+        self.movement.moved = false;
+        self.acted = false;
+    }
+
+    fn saved(&self) -> SavedUnit {
+        SavedUnit::Spy(self.clone())
+    }
+
+    as_any!();
+}
+
+impl Spy {
+    pub fn new(id: UnitId, name: String, owner: PlayerId, node: NodeId) -> Spy {
+        Spy {
+            id,
+            name,
+            owner,
+            movement: Movement::new(node, SPY_MOVEMENT_POINTS),
+            acted: false,
+        }
+    }
+}
+
+// Each spy reports the enemy armies it can see, and the state of any enemy city it's in
+pub fn spy_reports(app_state: &mut AppState, geo_and_cities: &GeoWithPathAndCities) {
+    let roads = &geo_and_cities.roads;
+    let turn = app_state.turn.turn;
+    let mut reports = Vec::new();
+    for spy in app_state.units.of_type::<Spy>() {
+        let at = spy.at(roads);
+        let armies: Vec<String> = app_state
+            .units
+            .of_type::<Army>()
+            .filter(|army| app_state.diplomacy.is_hostile(spy.owner, army.owner))
+            .filter(|army| Point::distance(army.at(roads).to_point(), at.to_point()) <= SPY_VISION)
+            .map(|army| format!("{} ({}) {}", army.name, app_state.players[army.owner].name, army.strength))
+            .collect();
+        if !armies.is_empty() {
+            reports.push((spy.owner, format!("{} sees {}", spy.name, armies.join(", "))));
+        }
+        if let Some(city) = enemy_city_at(app_state, geo_and_cities, spy.owner, at) {
+            let economy = &app_state.cities[city].economy;
+            reports.push((
+                spy.owner,
+                format!(
+                    "{} in {}: population {:.0}, income {:.1}, production {:.1}",
+                    spy.name,
+                    app_state.location(city).name,
+                    economy.population,
+                    economy.income,
+                    economy.production
+                ),
+            ));
+        }
+    }
+    reports.into_iter().for_each(|(owner, report)| app_state.messages.add_for(turn, owner, report));
+}
+
+fn enemy_city_at(app_state: &AppState, geo_and_cities: &GeoWithPathAndCities, owner: PlayerId, at: World) -> Option<CityId> {
+    let at = at.to_bng();
+    let (id, _) = geo_and_cities.cities.iter().enumerate().find(|(id, location)| {
+        let hostile = app_state.owner_of(*id).is_some_and(|city_owner| app_state.diplomacy.is_hostile(owner, city_owner));
+        hostile && Bng::from_scaled(location.x, location.y).distance(at) <= CITY_DISTANCE
+    })?;
+    Some(id)
+}
+
+/// Cut the city's output for a few turns. A spy that's caught is lost.
+pub fn sabotage(app_state: &mut AppState, geo_and_cities: &GeoWithPathAndCities, id: UnitId) {
+    let turn = app_state.turn.turn;
+    let (name, owner, at, acted) = match app_state.units.get_as::<Spy>(id) {
+        Some(spy) => (spy.name.clone(), spy.owner, spy.at(&geo_and_cities.roads), spy.acted),
+        None => return,
+    };
+    if acted {
+        app_state.messages.add_for(turn, owner, format!("{} has already acted this turn", name));
+        return;
+    }
+    let city = match enemy_city_at(app_state, geo_and_cities, owner, at) {
+        Some(city) => city,
+        None => {
+            app_state.messages.add_for(turn, owner, format!("{} needs to be in an enemy city", name));
+            return;
+        }
+    };
+    if let Some(spy) = app_state.units.get_as_mut::<Spy>(id) {
+        spy.acted = true;
+    }
+
+    let city_name = app_state.location(city).name.clone();
+    if app_state.rng.random_bool(SABOTAGE_CHANCE) {
+        let economy = &mut app_state.cities[city].economy;
+        economy.sabotaged = SABOTAGE_TURNS;
+        economy.update();
+        app_state.messages.add_for(turn, owner, format!("{} sabotaged {}", name, city_name));
+        if let Some(victim) = app_state.owner_of(city) {
+            app_state.messages.add_for(turn, victim, format!("{} was sabotaged", city_name));
+        }
+    } else {
+        app_state.messages.add(turn, format!("{} was caught in {}", name, city_name));
+        app_state.units.remove(id);
+        app_state.selected_unit = None;
+    }
 }
@@ -0,0 +1,181 @@
+use crate::app_state::AppState;
+use crate::game::army::Army;
+use crate::game::player::PlayerId;
+use crate::game::units::UnitId;
+use crate::geo::data::{GeoWithPathAndCities, WayClass};
+use crate::geo::roads::{NodeId, RoadEdge, RoadGraph};
+use crate::gfx::camera::Camera;
+use crate::gfx::skia::Skia;
+use skia_safe::paint::Style;
+use skia_safe::{Color, Paint, Path, Rect};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+// How far supply reaches from a friendly city, in km of B-road
+const SUPPLY_RANGE: f64 = 60.0;
+
+// Below this much supply an army is stretched, shown on the overlay
+const STRETCHED: f64 = 0.4;
+
+// Share of its men an army out of supply loses each turn, and the fewest
+const ATTRITION: f64 = 0.1;
+const MIN_ATTRITION: u32 = 1;
+
+// Supplied nodes are binned into cells this size (km) for the overlay
+const CELL_SIZE: f32 = 2.0;
+
+// Bigger roads carry supply further, this is how fast it runs out per km
+fn loss_per_km(class: &WayClass) -> f64 {
+    match class {
+        WayClass::Motorway => 0.4,
+        WayClass::ARoad => 0.6,
+        WayClass::BRoad => 1.0,
+        WayClass::Unclassified => 1.5,
+        WayClass::Unknown => 2.5,
+    }
+}
+
+fn supply_cost(edge: &RoadEdge) -> f64 {
+    edge.length as f64 / 1000.0 * loss_per_km(&edge.class)
+}
+
+/// How well supplied a player is across the road network. Supply runs out along the roads from
+/// their own and their allies' cities, and can't get past hostile armies.
+pub struct Supply {
+    costs: HashMap<NodeId, f64>,
+}
+
+impl Supply {
+    pub fn compute(app_state: &AppState, roads: &RoadGraph, player: PlayerId) -> Supply {
+        let friendly = |owner: PlayerId| owner == player || app_state.diplomacy.are_allies(player, owner);
+        let starts: Vec<NodeId> = (0..app_state.players.len())
+            .filter(|&owner| friendly(owner))
+            .flat_map(|owner| app_state.players[owner].cities.iter())
+            .filter_map(|&city| roads.nearest_node(app_state.location(city).world()))
+            .collect();
+        let blocked: HashSet<NodeId> = app_state
+            .units
+            .of_type::<Army>()
+            .filter(|army| army.strength > 0 && app_state.diplomacy.is_hostile(player, army.owner))
+            .map(|army| army.movement.node)
+            .collect();
+        let costs = roads.spread_from(&starts, SUPPLY_RANGE, supply_cost, |node| !blocked.contains(&node)).into_iter().collect();
+        Supply {
+            costs,
+        }
+    }
+
+    // 1 at a friendly city, falling to 0 at the edge of supply
+    pub fn level(&self, node: NodeId) -> f64 {
+        self.costs.get(&node).map_or(0.0, |cost| 1.0 - cost / SUPPLY_RANGE)
+    }
+
+    pub fn is_supplied(&self, node: NodeId) -> bool {
+        self.costs.contains_key(&node)
+    }
+}
+
+// Armies cut off from supply lose men, those with none left are gone
+pub fn attrition(app_state: &mut AppState, geo_and_cities: &GeoWithPathAndCities) {
+    let roads = &geo_and_cities.roads;
+    let turn = app_state.turn.turn;
+    // Everyone's supply as things stand, before any army is worn down, so the order players are taken in can't matter
+    let owners: BTreeSet<PlayerId> = app_state.units.of_type::<Army>().map(|army| army.owner).collect();
+    let cut_off: Vec<UnitId> = owners
+        .into_iter()
+        .flat_map(|player| {
+            let supply = Supply::compute(app_state, roads, player);
+            app_state
+                .units
+                .of_type::<Army>()
+                .filter(|army| army.owner == player && !supply.is_supplied(army.movement.node))
+                .map(|army| army.id)
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    for id in cut_off {
+        if let Some(army) = app_state.units.get_as_mut::<Army>(id) {
+            let lost = ((army.strength as f64 * ATTRITION).round() as u32).max(MIN_ATTRITION).min(army.strength);
+            army.strength -= lost;
+            let message = match army.strength {
+                0 => format!("{} starved out of supply", army.name),
+                _ => format!("{} is out of supply and lost {} men", army.name, lost),
+            };
+            app_state.messages.add(turn, message);
+        }
+    }
+
+    app_state.units.retain(|unit| unit.as_any().downcast_ref::<Army>().is_none_or(|army| army.strength > 0));
+    if app_state.selected_unit.is_some_and(|id| !app_state.units.contains(id)) {
+        app_state.selected_unit = None;
+    }
+}
+
+// What one player's supply looks like on the map, worked out once and drawn every frame
+pub struct SupplyOverlay {
+    pub player: PlayerId,
+    pub turn: u32,
+    supplied: Path,
+    stretched: Path,
+    // Positions of the player's armies with no supply
+    cut_off: Vec<NodeId>,
+}
+
+impl SupplyOverlay {
+    pub fn new(app_state: &AppState, roads: &RoadGraph, player: PlayerId) -> SupplyOverlay {
+        let supply = Supply::compute(app_state, roads, player);
+
+        // Best supply into each cell
+        let mut cells: HashMap<(i32, i32), f64> = HashMap::new();
+        for &node in supply.costs.keys() {
+            let p = roads.nodes[node].bng().to_world();
+            let cell = ((p.x / CELL_SIZE).floor() as i32, (p.y / CELL_SIZE).floor() as i32);
+            let entry = cells.entry(cell).or_insert(0.0);
+            *entry = entry.max(supply.level(node));
+        }
+        let mut supplied = Path::new();
+        let mut stretched = Path::new();
+        for ((cx, cy), level) in cells {
+            let rect = Rect::from_xywh(cx as f32 * CELL_SIZE, cy as f32 * CELL_SIZE, CELL_SIZE, CELL_SIZE);
+            if level >= STRETCHED {
+                supplied.add_rect(rect, None);
+            } else {
+                stretched.add_rect(rect, None);
+            }
+        }
+
+        SupplyOverlay {
+            player,
+            turn: app_state.turn.turn,
+            supplied,
+            stretched,
+            cut_off: app_state
+                .units
+                .of_type::<Army>()
+                .filter(|army| army.owner == player && !supply.is_supplied(army.movement.node))
+                .map(|army| army.movement.node)
+                .collect(),
+        }
+    }
+}
+
+pub fn draw_supply(skia: &mut Skia, camera: &Camera, overlay: &SupplyOverlay, roads: &RoadGraph) {
+    let zoom = camera.zoom;
+    let mut paint = Paint::default();
+    paint.set_anti_alias(true);
+    paint.set_style(Style::Fill);
+
+    let mut paint_cut_off = Paint::default();
+    paint_cut_off.set_anti_alias(true);
+    paint_cut_off.set_style(Style::Stroke);
+    paint_cut_off.set_stroke_width(3.0 / zoom);
+    paint_cut_off.set_color(Color::from_rgb(220, 30, 30));
+
+    let canvas = skia.get_canvas();
+    paint.set_color(Color::from_argb(80, 0, 160, 60));
+    canvas.draw_path(&overlay.supplied, &paint);
+    paint.set_color(Color::from_argb(80, 240, 180, 0));
+    canvas.draw_path(&overlay.stretched, &paint);
+    overlay.cut_off.iter().for_each(|&node| {
+        canvas.draw_circle(roads.nodes[node].bng().to_world().to_point(), 12.0 / zoom, &paint_cut_off);
+    });
+}
@@ -0,0 +1,143 @@
+use crate::app_state::AppState;
+use crate::game::combat::resolve_combat;
+use crate::game::economy::collect_income;
+use crate::game::events::GameEvent;
+use crate::game::player::{Player, PlayerType};
+use crate::game::replay::record_turn;
+use crate::game::scenario::run_triggers;
+use crate::game::script::{run_hook, run_hooks, Hook};
+use crate::game::spy::spy_reports;
+use crate::game::supply::attrition;
+use crate::game::units::{UnitContext, UnitId};
+use crate::game::victory::check_victory;
+use crate::geo::data::GeoWithPathAndCities;
+use serde::{Deserialize, Serialize};
+
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum Phase {
+    Orders,
+    Resolution,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TurnManager {
+    pub turn: u32,
+    pub current_player: Option<usize>,
+}
+
+impl TurnManager {
+    pub fn new(players: &mut [Player]) -> TurnManager {
+        let mut turn_manager = TurnManager {
+            turn: 0,
+            current_player: None,
+        };
+        turn_manager.start_turn(players);
+        turn_manager
+    }
+
+    pub fn start_turn(&mut self, players: &mut [Player]) {
+        self.turn += 1;
+        players.iter_mut().filter(|p| p.takes_turns()).for_each(|p| p.phase = Phase::Orders);
+        self.current_player = players.iter().position(|p| p.takes_turns());
+    }
+
+    /// Current player has finished giving orders, returns true once every player has.
+    pub fn end_player_turn(&mut self, players: &mut [Player]) -> bool {
+        if let Some(current) = self.current_player {
+            players[current].phase = Phase::Resolution;
+        }
+        self.current_player = players.iter().position(|p| p.takes_turns() && p.phase == Phase::Orders);
+        self.current_player.is_none()
+    }
+
+    pub fn status(&self, players: &[Player]) -> String {
+        match self.current_player {
+            Some(current) => format!("Turn {}: {} to give orders", self.turn, players[current].name),
+            None => format!("Turn {}: resolving", self.turn),
+        }
+    }
+}
+
+impl Player {
+    // Playing, or was until eliminated
+    pub fn takes_part(&self) -> bool {
+        !matches!(self.player_type, PlayerType::NotAssigned)
+    }
+
+    pub fn takes_turns(&self) -> bool {
+        self.takes_part() && !self.eliminated
+    }
+}
+
+// "End Turn" for whoever is giving orders, resolving the turn once they all have
+pub fn end_turn(app_state: &mut AppState, geo_and_cities: &GeoWithPathAndCities) {
+    if app_state.turn.end_player_turn(&mut app_state.players) {
+        resolve_turn(app_state, geo_and_cities);
+        if app_state.game_over.is_some() {
+            app_state.turn.current_player = None;
+            return;
+        }
+        app_state.turn.start_turn(&mut app_state.players);
+        let mut context = UnitContext {
+            turn: app_state.turn.turn,
+            roads: &geo_and_cities.roads,
+            messages: &mut app_state.messages,
+        };
+        app_state.units.turn_start(&mut context);
+        run_triggers(app_state, geo_and_cities);
+        run_hook(app_state, geo_and_cities, Hook::TurnStart);
+        record_turn(app_state);
+    }
+}
+
+// End of turn effects, in order
+fn resolve_turn(app_state: &mut AppState, geo_and_cities: &GeoWithPathAndCities) {
+    let mut context = UnitContext {
+        turn: app_state.turn.turn,
+        roads: &geo_and_cities.roads,
+        messages: &mut app_state.messages,
+    };
+    app_state.units.turn_end(&mut context);
+    let moved: Vec<UnitId> = app_state.units.iter().filter(|unit| unit.movement().moved).map(|unit| unit.id()).collect();
+    for &unit in &moved {
+        app_state.events.push(GameEvent::UnitMoved {
+            unit,
+        });
+    }
+    let moved = moved
+        .into_iter()
+        .map(|unit| Hook::UnitMoved {
+            unit,
+        })
+        .collect();
+    run_hooks(app_state, geo_and_cities, moved);
+
+    // Cities lost in battle show up as new ownership events
+    let before = app_state.events.len();
+    resolve_combat(app_state, geo_and_cities);
+    let captured: Vec<Hook> = app_state.events[before..]
+        .iter()
+        .filter_map(|event| match *event {
+            GameEvent::OwnershipChanged {
+                city,
+                from,
+                to,
+            } => Some(Hook::CityCaptured {
+                city,
+                from,
+                to,
+            }),
+            _ => None,
+        })
+        .collect();
+    run_hooks(app_state, geo_and_cities, captured);
+
+    attrition(app_state, geo_and_cities);
+    spy_reports(app_state, geo_and_cities);
+    collect_income(app_state);
+    run_hook(app_state, geo_and_cities, Hook::TurnEnd);
+    check_victory(app_state);
+    let turn = app_state.turn.turn;
+    app_state.diplomacy.expire(turn);
+    app_state.messages.add(turn, format!("Turn {} ended", turn));
+}
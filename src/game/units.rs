@@ -1,3 +1,296 @@
-pub trait UnitTrait {
+use crate::game::fog::Vision;
+use crate::game::messages::MessageLog;
+use crate::game::movement::Movement;
+use crate::game::player::{Player, PlayerId};
+use crate::game::save::SavedUnit;
+use crate::geo::crs::World;
+use crate::geo::roads::RoadGraph;
+use crate::gfx::camera::Camera;
+use crate::gfx::skia::Skia;
+use skia_safe::paint::{Cap, Join, Style};
+use skia_safe::utils::text_utils::Align;
+use skia_safe::{Color, Paint, Path, Point};
+use std::any::Any;
+use std::collections::BTreeMap;
+
+pub type UnitId = usize;
+
+const MARKER_PIXELS: f32 = 7.0;
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum Marker {
+    Circle,
+    Diamond,
+}
+
+// What a unit can reach while its turn hooks run
+pub struct UnitContext<'a> {
+    pub turn: u32,
+    pub roads: &'a RoadGraph,
+    pub messages: &'a mut MessageLog,
+}
+
+/// Everything the game loop needs from a unit. A new kind of unit implements this and
+/// is added to the registry, the turn, fog and drawing code then treat it like any other.
+pub trait UnitTrait: Any {
     fn get_name(&self) -> &str;
+    fn id(&self) -> UnitId;
+    fn owner(&self) -> PlayerId;
+    fn movement(&self) -> &Movement;
+    fn movement_mut(&mut self) -> &mut Movement;
+
+    // Gold per turn
+    fn upkeep(&self) -> f64;
+
+    // km
+    fn vision_radius(&self) -> f32;
+
+    fn marker(&self) -> Marker;
+
+    // Its road node, for anything the rules decide
+    fn at(&self, roads: &RoadGraph) -> World {
+        self.movement().at(roads)
+    }
+
+    // Drawing only, see Movement::position
+    fn position(&self, roads: &RoadGraph) -> World {
+        self.movement().position(roads)
+    }
+
+    // Seconds of driving per turn
+    fn movement_range(&self) -> f64 {
+        self.movement().points
+    }
+
+    // Shown next to the marker, but only to the owner or someone with intel on it
+    fn label(&self) -> Option<String> {
+        None
+    }
+
+    // Seen only by the owner, whatever else is nearby
+    fn is_covert(&self) -> bool {
+        false
+    }
+
+    // Sees the details of whatever is in its vision radius
+    fn gathers_intel(&self) -> bool {
+        false
+    }
+
+    fn on_turn_start(&mut self, _context: &mut UnitContext) {
+        self.movement_mut().moved = false;
+    }
+
+    // Orders are carried out once everyone has given them
+    fn on_turn_end(&mut self, context: &mut UnitContext) {
+        if !self.movement().is_moving() {
+            return;
+        }
+        self.movement_mut().advance(context.roads);
+        if !self.movement().is_moving() {
+            context.messages.add_for(context.turn, self.owner(), format!("{} reached its destination", self.get_name()));
+        }
+    }
+
+    // A copy of the unit for saves and state hashes, a new kind of unit adds its own SavedUnit variant
+    fn saved(&self) -> SavedUnit;
+
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+// The downcasting half of UnitTrait, the same for every unit type
+macro_rules! as_any {
+    () => {
+        fn as_any(&self) -> &dyn std::any::Any {
+            self
+        }
+
+        fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+            self
+        }
+    };
+}
+pub(crate) use as_any;
+
+#[derive(Default)]
+pub struct UnitRegistry {
+    units: BTreeMap<UnitId, Box<dyn UnitTrait>>,
+    next_id: UnitId,
+}
+
+impl UnitRegistry {
+    // Put back units from a save, ids carry on from where they left off
+    pub fn restore(units: Vec<Box<dyn UnitTrait>>, next_id: UnitId) -> UnitRegistry {
+        UnitRegistry {
+            units: units.into_iter().map(|unit| (unit.id(), unit)).collect(),
+            next_id,
+        }
+    }
+
+    pub fn next_id(&self) -> UnitId {
+        self.next_id
+    }
+
+    pub fn add<U: UnitTrait>(&mut self, make: impl FnOnce(UnitId) -> U) -> UnitId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.units.insert(id, Box::new(make(id)));
+        id
+    }
+
+    pub fn remove(&mut self, id: UnitId) -> Option<Box<dyn UnitTrait>> {
+        self.units.remove(&id)
+    }
+
+    pub fn newest(&self) -> Option<UnitId> {
+        self.units.keys().next_back().copied()
+    }
+
+    pub fn contains(&self, id: UnitId) -> bool {
+        self.units.contains_key(&id)
+    }
+
+    pub fn get(&self, id: UnitId) -> Option<&dyn UnitTrait> {
+        self.units.get(&id).map(|unit| unit.as_ref())
+    }
+
+    pub fn get_mut(&mut self, id: UnitId) -> Option<&mut dyn UnitTrait> {
+        self.units.get_mut(&id).map(|unit| unit.as_mut())
+    }
+
+    pub fn get_as<U: UnitTrait>(&self, id: UnitId) -> Option<&U> {
+        self.units.get(&id).and_then(|unit| unit.as_any().downcast_ref())
+    }
+
+    pub fn get_as_mut<U: UnitTrait>(&mut self, id: UnitId) -> Option<&mut U> {
+        self.units.get_mut(&id).and_then(|unit| unit.as_any_mut().downcast_mut())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn UnitTrait> {
+        self.units.values().map(|unit| unit.as_ref())
+    }
+
+    // Every unit of one kind, in id order
+    pub fn of_type<U: UnitTrait>(&self) -> impl Iterator<Item = &U> {
+        self.units.values().filter_map(|unit| unit.as_any().downcast_ref())
+    }
+
+    pub fn ids_of_type<U: UnitTrait>(&self) -> Vec<UnitId> {
+        self.of_type::<U>().map(|unit| unit.id()).collect()
+    }
+
+    pub fn retain(&mut self, mut keep: impl FnMut(&dyn UnitTrait) -> bool) {
+        self.units.retain(|_, unit| keep(unit.as_ref()));
+    }
+
+    pub fn upkeep(&self, owner: PlayerId) -> f64 {
+        self.iter().filter(|unit| unit.owner() == owner).map(|unit| unit.upkeep()).sum()
+    }
+
+    // Nearest of the owner's units within radius of point, in canvas units
+    pub fn unit_at(&self, owner: PlayerId, roads: &RoadGraph, point: World, radius: f32) -> Option<UnitId> {
+        self.iter()
+            .filter(|unit| unit.owner() == owner)
+            .map(|unit| (unit.id(), Point::distance(unit.position(roads).to_point(), point.to_point())))
+            .filter(|(_, distance)| *distance <= radius)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(id, _)| id)
+    }
+
+    pub fn turn_start(&mut self, context: &mut UnitContext) {
+        self.units.values_mut().for_each(|unit| unit.on_turn_start(context));
+    }
+
+    pub fn turn_end(&mut self, context: &mut UnitContext) {
+        self.units.values_mut().for_each(|unit| unit.on_turn_end(context));
+    }
+}
+
+// Other players' units only show within sight, and their labels only where there's intel
+pub fn draw_units(
+    skia: &mut Skia,
+    camera: &Camera,
+    units: &UnitRegistry,
+    selected: Option<UnitId>,
+    players: &[Player],
+    vision: Option<&Vision>,
+    roads: &RoadGraph,
+) {
+    let zoom = camera.zoom;
+    let font = skia.font_label.clone();
+
+    let mut paint_route = Paint::default();
+    paint_route.set_anti_alias(true);
+    paint_route.set_style(Style::Stroke);
+    paint_route.set_stroke_cap(Cap::Round);
+    paint_route.set_stroke_join(Join::Round);
+    paint_route.set_stroke_width(3.0 / zoom);
+
+    let mut paint_marker = Paint::default();
+    paint_marker.set_anti_alias(true);
+    paint_marker.set_style(Style::Fill);
+
+    let mut paint_outline = Paint::default();
+    paint_outline.set_anti_alias(true);
+    paint_outline.set_style(Style::Stroke);
+    paint_outline.set_stroke_width(1.5 / zoom);
+
+    let mut paint_text = Paint::default();
+    paint_text.set_anti_alias(true);
+    paint_text.set_style(Style::Fill);
+    paint_text.set_color(Color::BLACK);
+
+    let canvas = skia.get_canvas();
+    units.iter().for_each(|unit| {
+        let position = unit.position(roads);
+        let selected = selected == Some(unit.id());
+        // A selected unit's route may cross the window even when the unit is off it
+        if !selected && !camera.is_visible(position, MARKER_PIXELS) {
+            return;
+        }
+        let (visible, intel) = match vision {
+            Some(vision) if vision.player != unit.owner() => (!unit.is_covert() && vision.can_see(position), vision.has_intel(position)),
+            _ => (true, true),
+        };
+        if !visible {
+            return;
+        }
+        let colour = players[unit.owner()].colour;
+
+        if selected && unit.movement().is_moving() {
+            paint_route.set_color(colour.with_a(160));
+            canvas.draw_path(&unit.movement().route_path(roads), &paint_route);
+        }
+
+        let p = position.to_point();
+        let size = MARKER_PIXELS / zoom;
+        paint_marker.set_color(colour);
+        paint_outline.set_color(if selected {
+            Color::YELLOW
+        } else {
+            Color::WHITE
+        });
+        match unit.marker() {
+            Marker::Circle => {
+                canvas.draw_circle(p, size, &paint_marker);
+                canvas.draw_circle(p, size, &paint_outline);
+            }
+            Marker::Diamond => {
+                let mut diamond = Path::new();
+                diamond.add_poly(&[Point::new(p.x, p.y - size), Point::new(p.x + size, p.y), Point::new(p.x, p.y + size), Point::new(p.x - size, p.y)], true);
+                canvas.draw_path(&diamond, &paint_marker);
+                canvas.draw_path(&diamond, &paint_outline);
+            }
+        }
+
+        if let Some(label) = unit.label() {
+            let label = if intel {
+                label
+            } else {
+                String::from("?")
+            };
+            canvas.draw_text_align(label, Point::new(p.x + size * 1.5, p.y + size / 2.0), &font, &paint_text, Align::Left);
+        }
+    });
 }
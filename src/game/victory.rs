@@ -0,0 +1,205 @@
+use crate::app_state::AppState;
+use crate::game::army::Army;
+use crate::game::player::PlayerId;
+use serde::{Deserialize, Serialize};
+
+// Score per thousand people, per city, per hundred soldiers and per hundred gold
+const SCORE_PER_THOUSAND: f64 = 1.0;
+const SCORE_PER_CITY: f64 = 20.0;
+const SCORE_PER_HUNDRED_SOLDIERS: f64 = 10.0;
+const SCORE_PER_HUNDRED_GOLD: f64 = 5.0;
+
+/// Ways to win, chosen at setup. Checked at the end of every turn, the first to be met ends the game.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct VictoryConditions {
+    // Share of the whole population, 0..1
+    pub population_share: Option<f64>,
+    // Hold every player's starting city
+    pub capitals: bool,
+    // Be the last one left
+    pub elimination: bool,
+    // Highest score when the turn is over
+    pub turn_limit: Option<u32>,
+}
+
+impl Default for VictoryConditions {
+    fn default() -> VictoryConditions {
+        VictoryConditions {
+            population_share: Some(0.5),
+            capitals: true,
+            elimination: true,
+            turn_limit: Some(200),
+        }
+    }
+}
+
+impl VictoryConditions {
+    // Choices offered on the setup screen, in order
+    pub fn presets() -> Vec<VictoryConditions> {
+        let none = VictoryConditions {
+            population_share: None,
+            capitals: false,
+            elimination: false,
+            turn_limit: None,
+        };
+        vec![
+            VictoryConditions::default(),
+            VictoryConditions {
+                elimination: true,
+                ..none.clone()
+            },
+            VictoryConditions {
+                population_share: Some(0.5),
+                ..none.clone()
+            },
+            VictoryConditions {
+                capitals: true,
+                ..none.clone()
+            },
+            VictoryConditions {
+                turn_limit: Some(100),
+                ..none
+            },
+        ]
+    }
+
+    pub fn next_preset(&self) -> VictoryConditions {
+        let presets = VictoryConditions::presets();
+        let index = presets.iter().position(|preset| preset == self).map_or(0, |index| (index + 1) % presets.len());
+        presets[index].clone()
+    }
+
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(share) = self.population_share {
+            parts.push(format!("{:.0}% of people", share * 100.0));
+        }
+        if self.capitals {
+            parts.push(String::from("capitals"));
+        }
+        if self.elimination {
+            parts.push(String::from("last standing"));
+        }
+        if let Some(turns) = self.turn_limit {
+            parts.push(format!("score after {} turns", turns));
+        }
+        if parts.is_empty() {
+            String::from("none")
+        } else {
+            parts.join(", ")
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum Victory {
+    Population,
+    Capitals,
+    Elimination,
+    Score,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct GameOver {
+    pub turn: u32,
+    pub winner: PlayerId,
+    pub victory: Victory,
+}
+
+impl GameOver {
+    pub fn summary(&self, app_state: &AppState) -> String {
+        let how = match self.victory {
+            Victory::Population => "ruling over the most people",
+            Victory::Capitals => "holding every capital",
+            Victory::Elimination => "outlasting everyone",
+            Victory::Score => "scoring highest",
+        };
+        format!("{} wins on turn {} by {}", app_state.players[self.winner].name, self.turn, how)
+    }
+}
+
+// How a player stands, for the game over screen and the score
+pub struct Standing {
+    pub player: PlayerId,
+    pub cities: usize,
+    pub population: f64,
+    pub units: usize,
+    pub strength: u32,
+    pub score: f64,
+}
+
+pub fn standings(app_state: &AppState) -> Vec<Standing> {
+    app_state
+        .players
+        .iter()
+        .enumerate()
+        .filter(|(_, player)| player.takes_part())
+        .map(|(id, player)| {
+            let population: f64 = player.cities.iter().map(|&city| app_state.cities[city].economy.population).sum();
+            let strength: u32 = app_state.units.of_type::<Army>().filter(|army| army.owner == id).map(|army| army.strength).sum();
+            let score = population / 1000.0 * SCORE_PER_THOUSAND
+                + player.cities.len() as f64 * SCORE_PER_CITY
+                + strength as f64 / 100.0 * SCORE_PER_HUNDRED_SOLDIERS
+                + player.treasury.max(0.0) / 100.0 * SCORE_PER_HUNDRED_GOLD;
+            Standing {
+                player: id,
+                cities: player.cities.len(),
+                population,
+                units: app_state.units.iter().filter(|unit| unit.owner() == id).count(),
+                strength,
+                score,
+            }
+        })
+        .collect()
+}
+
+/// End of turn: knock out anyone with nothing left, then see whether someone has won.
+pub fn check_victory(app_state: &mut AppState) {
+    let turn = app_state.turn.turn;
+    for id in 0..app_state.players.len() {
+        let player = &app_state.players[id];
+        if player.takes_turns() && player.cities.is_empty() && !app_state.units.iter().any(|unit| unit.owner() == id) {
+            app_state.players[id].eliminated = true;
+            app_state.messages.add(turn, format!("{} has been eliminated", app_state.players[id].name));
+        }
+    }
+
+    let conditions = app_state.victory.clone();
+    let standings = standings(app_state);
+    let alive: Vec<&Standing> = standings.iter().filter(|standing| !app_state.players[standing.player].eliminated).collect();
+    // Only cities in play, a scenario may leave some out
+    let total_population: f64 = app_state.owners.keys().map(|&city| app_state.cities[city].economy.population).sum();
+
+    let mut result = None;
+    if conditions.elimination && alive.len() == 1 {
+        result = Some((alive[0].player, Victory::Elimination));
+    }
+    if result.is_none() && conditions.capitals {
+        // A scenario without capitals can't be won this way, rather than won by everyone at once
+        let capitals: Vec<_> = app_state.players.iter().filter_map(|player| player.capital).collect();
+        let holder = alive.iter().find(|standing| capitals.iter().all(|&capital| app_state.owner_of(capital) == Some(standing.player)));
+        result = holder.filter(|_| !capitals.is_empty()).map(|standing| (standing.player, Victory::Capitals));
+    }
+    if let (None, Some(share)) = (&result, conditions.population_share) {
+        let biggest = alive.iter().max_by(|a, b| a.population.total_cmp(&b.population));
+        result = biggest
+            .filter(|standing| total_population > 0.0 && standing.population / total_population >= share)
+            .map(|standing| (standing.player, Victory::Population));
+    }
+    if let (None, Some(limit)) = (&result, conditions.turn_limit) {
+        if turn >= limit {
+            let best = alive.iter().max_by(|a, b| a.score.total_cmp(&b.score));
+            result = best.map(|standing| (standing.player, Victory::Score));
+        }
+    }
+
+    if let Some((winner, victory)) = result {
+        let game_over = GameOver {
+            turn,
+            winner,
+            victory,
+        };
+        app_state.messages.add(turn, game_over.summary(app_state));
+        app_state.game_over = Some(game_over);
+    }
+}
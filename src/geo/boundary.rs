@@ -1,5 +1,5 @@
+use crate::geo::crs::Bng;
 use crate::geo::data::{Geo, GeoWithPath, Way, WayClass, WayPoint, WaySkia};
-use crate::geo::load::RATIO_ADJUST;
 use crate::geo::ways::{get_geometry, path_from_ways};
 use crate::gfx::skia::Skia;
 use gdal::vector::LayerAccess;
@@ -51,6 +51,20 @@ pub fn load_boundaries() -> Vec<Path> {
     vec
 }
 
+// GB land polygons from merged_by_region.geojson, BNG metres
+pub fn load_land() -> Path {
+    let file = File::open("data/Geo.cbor").expect("Unable to open land file");
+    let reader = BufReader::new(file);
+    let regions: Vec<Geo> = from_reader(reader).expect("Unable to read land file");
+
+    let mut land = Path::new();
+    regions.iter().flat_map(|region| region.geo.iter()).for_each(|polygon| {
+        let points: Vec<Point> = polygon.exterior().coords().map(|c| Bng::new(c.x, c.y).to_world().to_point()).collect();
+        land.add_poly(&points, true);
+    });
+    land
+}
+
 pub fn draw_boundaries(skia: &mut Skia, boundaries: &[Path]) {
     let mut paint = Paint::default();
     paint.set_anti_alias(true);
@@ -1,17 +1,18 @@
 use crate::app_state::AppState;
+use crate::geo::crs::{Bng, World};
 use crate::geo::data::Location;
-use crate::geo::load::RATIO_ADJUST;
+use crate::gfx::camera::Camera;
 use crate::gfx::skia::Skia;
 use serde_cbor::from_reader;
 use skia_safe::image_filters::drop_shadow_only;
 use skia_safe::paint::Style;
 use skia_safe::utils::text_utils::Align;
-use skia_safe::{scalar, Color, Paint, Point, Vector};
+use skia_safe::{Color, Paint, Point, Vector};
 use std::fs::File;
 use std::io::BufReader;
 use std::rc::Rc;
 
-pub fn draw_all_cities(skia: &mut Skia, app_state: &AppState) {
+pub fn draw_all_cities(skia: &mut Skia, camera: &Camera, app_state: &AppState) {
     let font = &skia.font_label.clone();
     let font_bold = &skia.font_label_bold.clone();
     let _descent = font.metrics().1.descent;
@@ -31,10 +32,16 @@ pub fn draw_all_cities(skia: &mut Skia, app_state: &AppState) {
 
     let canvas = skia.get_canvas();
     app_state.players.iter().for_each(|player| {
-        player.cities.iter().for_each(|city| {
-            let l = &city.location;
+        paint.set_color(player.colour);
+        player.cities.iter().for_each(|&city| {
+            let l = app_state.location(city);
+            let p = Bng::from_scaled(l.x, l.y).to_world();
             let (w, bounds) = font.measure_text(&l.name, Some(&paint));
-            let p2 = Point::new(l.x as scalar - w / 2.0, -l.y as scalar - bounds.y() / 2.0);
+            // Labels are centred on their city, so one just off the window may still reach into it
+            if !camera.is_visible(p, w * camera.zoom / 2.0) {
+                return;
+            }
+            let p2 = Point::new(p.x - w / 2.0, p.y - bounds.y() / 2.0);
             canvas.draw_text_align(&l.name, p2, font_bold, &paint_shadow, Align::Left);
             canvas.draw_text_align(&l.name, p2, font_bold, &paint, Align::Left);
         })
@@ -53,8 +60,7 @@ pub fn load_cities_cbor_file(file_path: &str, radius: f64) -> Vec<Rc<Location>>
     let mut locations_out: Vec<Rc<Location>> = Vec::new();
     for mut location in locations.into_iter() {
         if location.population >= 25000 {
-            location.x /= RATIO_ADJUST as f64;
-            location.y /= RATIO_ADJUST as f64;
+            (location.x, location.y) = Bng::new(location.x, location.y).to_scaled();
             let mut minimum_distance = f64::INFINITY;
             for location_out in &locations_out {
                 let dist = calculate_distance(&location, location_out);
@@ -74,6 +80,16 @@ pub fn load_cities_cbor_file(file_path: &str, radius: f64) -> Vec<Rc<Location>>
     locations_out
 }
 
+// Nearest city label within radius of point, in canvas units
+pub fn city_at(cities: &[Rc<Location>], point: World, radius: f32) -> Option<Rc<Location>> {
+    cities
+        .iter()
+        .map(|city| (city, Point::distance(Bng::from_scaled(city.x, city.y).to_world().to_point(), point.to_point())))
+        .filter(|(_, distance)| *distance <= radius)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(city, _)| city.clone())
+}
+
 fn calculate_distance(city1: &Location, city2: &Location) -> f64 {
     let dx = city1.x - city2.x;
     let dy = city1.y - city2.y;
@@ -0,0 +1,225 @@
+use proj::{Coord, Proj};
+use serde::{Deserialize, Serialize};
+use skia_safe::{Point, Vector};
+use std::error::Error;
+
+// Everything on the canvas is in kilometres rather than metres
+pub const RATIO_ADJUST: f32 = 1000.0;
+
+pub const BNG_CRS: &str = "EPSG:27700";
+pub const WGS84_CRS: &str = "EPSG:4326";
+
+// Extent of the OS national grid, in metres
+pub const GRID_WIDTH: f64 = 700000.0;
+pub const GRID_HEIGHT: f64 = 1300000.0;
+
+/// British National Grid, metres.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Bng {
+    pub easting: f64,
+    pub northing: f64,
+}
+
+/// Canvas units, BNG kilometres with Y pointing down the screen.
+#[derive(Copy, Clone, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub struct World {
+    pub x: f32,
+    pub y: f32,
+}
+
+/// Window pixels, before the High-DPI scale.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Screen {
+    pub x: f32,
+    pub y: f32,
+}
+
+/// WGS84, degrees.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LatLon {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+impl Bng {
+    pub fn new(easting: f64, northing: f64) -> Bng {
+        Bng {
+            easting,
+            northing,
+        }
+    }
+
+    // Ways and cities are stored scaled down by RATIO_ADJUST, but with north still up
+    pub fn from_scaled(x: f64, y: f64) -> Bng {
+        Bng::new(x * RATIO_ADJUST as f64, y * RATIO_ADJUST as f64)
+    }
+
+    pub fn to_scaled(self) -> (f64, f64) {
+        (self.easting / RATIO_ADJUST as f64, self.northing / RATIO_ADJUST as f64)
+    }
+
+    pub fn to_world(self) -> World {
+        World {
+            x: (self.easting / RATIO_ADJUST as f64) as f32,
+            y: (-self.northing / RATIO_ADJUST as f64) as f32,
+        }
+    }
+
+    pub fn distance(self, other: Bng) -> f64 {
+        let de = self.easting - other.easting;
+        let dn = self.northing - other.northing;
+        (de * de + dn * dn).sqrt()
+    }
+
+    /// OS grid reference with the given number of digits, e.g. "SK 123 456" for 6.
+    /// None off the grid, or unless digits is an even number from 2 to 10.
+    pub fn to_grid_ref(self, digits: usize) -> Option<String> {
+        if !matches!(digits, 2 | 4 | 6 | 8 | 10) {
+            return None;
+        }
+        if !(0.0..GRID_WIDTH).contains(&self.easting) || !(0.0..GRID_HEIGHT).contains(&self.northing) {
+            return None;
+        }
+
+        // 100km square letters, the grid skips I
+        let e100k = (self.easting / 100000.0).floor() as i32;
+        let n100k = (self.northing / 100000.0).floor() as i32;
+        let mut l1 = (19 - n100k) - (19 - n100k) % 5 + (e100k + 10) / 5;
+        let mut l2 = (19 - n100k) * 5 % 25 + e100k % 5;
+        if l1 > 7 {
+            l1 += 1;
+        }
+        if l2 > 7 {
+            l2 += 1;
+        }
+        let letter = |l: i32| (b'A' + l as u8) as char;
+
+        // Remaining digits within the square
+        let half = digits / 2;
+        let divisor = 10f64.powi(5 - half as i32);
+        let e = ((self.easting % 100000.0) / divisor).floor() as u32;
+        let n = ((self.northing % 100000.0) / divisor).floor() as u32;
+        Some(format!("{}{} {:0width$} {:0width$}", letter(l1), letter(l2), e, n, width = half))
+    }
+}
+
+impl World {
+    pub fn new(x: f32, y: f32) -> World {
+        World {
+            x,
+            y,
+        }
+    }
+
+    pub fn from_point(point: Point) -> World {
+        World::new(point.x, point.y)
+    }
+
+    pub fn to_point(self) -> Point {
+        Point::new(self.x, self.y)
+    }
+
+    pub fn to_bng(self) -> Bng {
+        Bng::new(self.x as f64 * RATIO_ADJUST as f64, -self.y as f64 * RATIO_ADJUST as f64)
+    }
+
+    pub fn to_screen(self, centre: Vector, zoom: f32, target: Point) -> Screen {
+        Screen {
+            x: (self.x - target.x) * zoom + centre.x,
+            y: (self.y - target.y) * zoom + centre.y,
+        }
+    }
+}
+
+impl Screen {
+    pub fn new(x: f32, y: f32) -> Screen {
+        Screen {
+            x,
+            y,
+        }
+    }
+
+    pub fn to_world(self, centre: Vector, zoom: f32, target: Point) -> World {
+        World {
+            x: (self.x - centre.x) / zoom + target.x,
+            y: (self.y - centre.y) / zoom + target.y,
+        }
+    }
+}
+
+impl LatLon {
+    pub fn to_dms(self) -> String {
+        let dms = |value: f64, positive: char, negative: char| {
+            let hemisphere = if value >= 0.0 {
+                positive
+            } else {
+                negative
+            };
+            let value = value.abs();
+            let degrees = value.floor();
+            let minutes = ((value - degrees) * 60.0).floor();
+            let seconds = (value - degrees - minutes / 60.0) * 3600.0;
+            format!("{}°{:02}'{:04.1}\"{}", degrees, minutes, seconds, hemisphere)
+        };
+        format!("{} {}", dms(self.lat, 'N', 'S'), dms(self.lon, 'E', 'W'))
+    }
+}
+
+// Projections are expensive to create so keep hold of them
+pub struct Crs {
+    to_lat_lon: Proj,
+    from_lat_lon: Proj,
+}
+
+impl Default for Crs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Crs {
+    pub fn new() -> Crs {
+        Crs {
+            to_lat_lon: Proj::new_known_crs(BNG_CRS, WGS84_CRS, None).expect("Failed to create projection"),
+            from_lat_lon: Proj::new_known_crs(WGS84_CRS, BNG_CRS, None).expect("Failed to create projection"),
+        }
+    }
+
+    pub fn bng_to_lat_lon(&self, bng: Bng) -> Option<LatLon> {
+        let c = self.to_lat_lon.convert((bng.easting, bng.northing)).ok()?;
+        Some(LatLon {
+            lat: c.y(),
+            lon: c.x(),
+        })
+    }
+
+    pub fn lat_lon_to_bng(&self, lat_lon: LatLon) -> Option<Bng> {
+        let c = self.from_lat_lon.convert((lat_lon.lon, lat_lon.lat)).ok()?;
+        Some(Bng::new(c.x(), c.y()))
+    }
+}
+
+// One-off conversion from any known CRS, used when loading georeferenced data
+pub fn project_to_bng(source_crs: &str, x: f64, y: f64) -> Result<Bng, Box<dyn Error>> {
+    if source_crs == BNG_CRS {
+        return Ok(Bng::new(x, y));
+    }
+    let proj = Proj::new_known_crs(source_crs, BNG_CRS, None)?;
+    let c = proj.convert((x, y))?;
+    Ok(Bng::new(c.x(), c.y()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid_refs_take_an_even_number_of_digits() {
+        let bng = Bng::new(437000.0, 336000.0);
+        assert_eq!(bng.to_grid_ref(6).as_deref(), Some("SK 370 360"));
+        assert_eq!(bng.to_grid_ref(10).as_deref(), Some("SK 37000 36000"));
+        assert_eq!(bng.to_grid_ref(5), None);
+        assert_eq!(bng.to_grid_ref(0), None);
+        assert_eq!(bng.to_grid_ref(12), None);
+    }
+}
@@ -1,6 +1,9 @@
+use crate::geo::crs::{Bng, World};
+use crate::geo::dem::Dem;
+use crate::geo::roads::RoadGraph;
 use geo::Polygon;
 use serde::{Deserialize, Serialize};
-use skia_safe::{Image, Path};
+use skia_safe::Path;
 use std::collections::HashMap;
 use std::rc::Rc;
 
@@ -12,6 +15,12 @@ pub struct Location {
     pub population: i32,
 }
 
+impl Location {
+    pub fn world(&self) -> World {
+        Bng::from_scaled(self.x, self.y).to_world()
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Geo {
     pub geo: Vec<Polygon>,
@@ -20,8 +29,10 @@ pub struct Geo {
 pub struct GeoWithPathAndCities {
     pub cities: Vec<Rc<Location>>,
     pub ways: HashMap<WayClass, Vec<WaySkia>>,
-    pub dem: Image,
+    pub dem: Dem,
     pub boundaries: Vec<Path>,
+    pub land: Path,
+    pub roads: RoadGraph,
 }
 
 pub struct GeoWithPath {
@@ -37,6 +48,19 @@ pub enum WayClass {
     Unknown,
 }
 
+impl WayClass {
+    // Typical free flowing speed
+    pub fn speed_kph(&self) -> f32 {
+        match self {
+            WayClass::Motorway => 110.0,
+            WayClass::ARoad => 80.0,
+            WayClass::BRoad => 60.0,
+            WayClass::Unclassified => 40.0,
+            WayClass::Unknown => 20.0,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum WayForm {
     SingleCarriageway,
@@ -47,6 +71,20 @@ pub enum WayForm {
     PublicTransportWay,
 }
 
+impl WayForm {
+    // Fraction of the class speed, zero for ways you can't drive
+    pub fn speed_factor(&self) -> f32 {
+        match self {
+            WayForm::DualCarriageway => 1.0,
+            WayForm::CollapsedDualCarriageway => 1.0,
+            WayForm::SingleCarriageway => 0.85,
+            WayForm::SlipRoad => 0.6,
+            WayForm::Roundabout => 0.35,
+            WayForm::PublicTransportWay => 0.0,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct WayPoint {
     pub is_start: bool,
@@ -68,3 +106,61 @@ pub struct WaySkia {
     pub _form: WayForm,
     pub path: Path,
 }
+
+// A handful of towns strung along one road, enough to play a game on in tests
+#[cfg(test)]
+pub fn test_geo() -> GeoWithPathAndCities {
+    use crate::geo::roads::{RoadEdge, RoadNode};
+    use skia_safe::Rect;
+
+    let towns = [("Ashby", 40000), ("Barton", 15000), ("Coleford", 25000), ("Dunmore", 60000), ("Eastwick", 20000), ("Fenton", 35000)];
+    let cities: Vec<Rc<Location>> = towns
+        .iter()
+        .enumerate()
+        .map(|(i, (name, population))| {
+            Rc::new(Location {
+                name: name.to_string(),
+                x: 400.0 + 20.0 * i as f64,
+                y: 300.0,
+                population: *population,
+            })
+        })
+        .collect();
+
+    let mut roads = RoadGraph::default();
+    roads.nodes = cities
+        .iter()
+        .map(|city| RoadNode {
+            x: city.x,
+            y: city.y,
+        })
+        .collect();
+    roads.edges = (1..cities.len())
+        .map(|i| RoadEdge {
+            from: i - 1,
+            to: i,
+            length: 20000.0,
+            class: WayClass::ARoad,
+            form: WayForm::SingleCarriageway,
+            // Straight from town to town, so moves have a trail to animate along
+            way_points: [(i - 1, true), (i, false)]
+                .iter()
+                .map(|&(town, is_start)| WayPoint {
+                    is_start,
+                    x: cities[town].x,
+                    y: cities[town].y,
+                })
+                .collect(),
+        })
+        .collect();
+    roads.build_index();
+
+    GeoWithPathAndCities {
+        cities,
+        ways: HashMap::new(),
+        dem: Dem::flat(Rect::new(380.0, -320.0, 520.0, -280.0)),
+        boundaries: Vec::new(),
+        land: Path::new(),
+        roads,
+    }
+}
@@ -1,47 +1,260 @@
-use crate::geo::load::RATIO_ADJUST;
-use crate::gfx::skia::Skia;
-use proj::{Coord, Proj};
+use crate::geo::crs::{project_to_bng, World, WGS84_CRS};
+use crate::gfx::skia::{load_image_from_file, Skia};
+use gdal::Dataset;
 use skia_safe::paint::Style;
-use skia_safe::{Color, FilterMode, Image, MipmapMode, Paint, Rect, SamplingOptions, Vector};
+use skia_safe::{surfaces, Color, Contains, FilterMode, IRect, Image, MipmapMode, Paint, Rect, SamplingOptions, Vector};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
 
-pub fn draw_dem(skia: &mut Skia, image: &Image) {
+const TILE_SIZE: i32 = 512;
+
+// Shading is kept on the CPU at roughly this width for terrain lookups
+const SHADE_WIDTH: i32 = 2048;
+
+// Average shading change to a neighbouring pixel that counts as the roughest terrain
+const ROUGHEST: f32 = 48.0;
+
+// The hillshade is exported in lat/lon unless a GeoTIFF tells us otherwise
+const DEFAULT_SOURCE_CRS: &str = WGS84_CRS;
+
+// Where the hillshade covers when nothing next to it says, degrees
+const DEFAULT_NORTH: f64 = 60.655938;
+const DEFAULT_SOUTH: f64 = 49.908067;
+const DEFAULT_WEST: f64 = -9.222697;
+const DEFAULT_EAST: f64 = 1.559322;
+
+pub struct DemTile {
+    pub bounds: Rect,
+    pub image: Image,
+}
+
+pub struct DemLevel {
+    pub pixel_size: f32,
+    pub tiles: Vec<DemTile>,
+}
+
+// Greyscale copy of one pyramid level
+struct Shade {
+    width: i32,
+    height: i32,
+    values: Vec<u8>,
+}
+
+pub struct Dem {
+    pub extent: Rect,
+    pub levels: Vec<DemLevel>,
+    shade: Shade,
+}
+
+impl Dem {
+    pub fn load(image_path: &str) -> Result<Dem, Box<dyn Error>> {
+        let image = load_image_from_file(image_path).to_raster_image(None).ok_or("Unable to decode DEM image")?;
+        let (geo_transform, source_crs) = read_geo_transform(image_path, image.width(), image.height())?;
+        let extent = extent_to_world(&geo_transform, image.width(), image.height(), &source_crs)?;
+
+        // Build a pyramid, halving each time until the whole thing fits in a single tile
+        let mut levels = Vec::new();
+        let mut level_image = image;
+        let mut shade = None;
+        loop {
+            if shade.is_none() && level_image.width() <= SHADE_WIDTH {
+                shade = read_shade(&level_image);
+            }
+            let pixel_size = extent.width() / level_image.width() as f32;
+            levels.push(DemLevel {
+                pixel_size,
+                tiles: tile_image(&level_image, &extent),
+            });
+            if level_image.width() <= TILE_SIZE && level_image.height() <= TILE_SIZE {
+                break;
+            }
+            level_image = downsample(&level_image)?;
+        }
+
+        println!("DEM has {} levels, extent {:?}", levels.len(), extent);
+        Ok(Dem {
+            extent,
+            levels,
+            shade: shade.unwrap_or(Shade {
+                width: 0,
+                height: 0,
+                values: Vec::new(),
+            }),
+        })
+    }
+
+    fn level_for(&self, pixels_per_unit: f32) -> &DemLevel {
+        // Coarsest level that still has at least one image pixel per screen pixel
+        let wanted = 1.0 / pixels_per_unit;
+        self.levels.iter().rev().find(|level| level.pixel_size <= wanted).unwrap_or(&self.levels[0])
+    }
+
+    /// How broken up the ground is around a point, 0 for flat to 1 for the steepest hillsides.
+    /// Flat ground shades evenly, so this is the average change in shading to the neighbouring pixels.
+    pub fn ruggedness(&self, point: World) -> f32 {
+        let shade = &self.shade;
+        if shade.values.is_empty() || !self.extent.contains(point.to_point()) {
+            return 0.0;
+        }
+        let x = ((point.x - self.extent.left) / self.extent.width() * shade.width as f32) as i32;
+        let y = ((point.y - self.extent.top) / self.extent.height() * shade.height as f32) as i32;
+        let value = |x: i32, y: i32| shade.values[(y.clamp(0, shade.height - 1) * shade.width + x.clamp(0, shade.width - 1)) as usize] as f32;
+
+        let centre = value(x, y);
+        let mut total = 0.0;
+        for (dx, dy) in [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)] {
+            total += (value(x + dx, y + dy) - centre).abs();
+        }
+        (total / 8.0 / ROUGHEST).min(1.0)
+    }
+}
+
+#[cfg(test)]
+impl Dem {
+    // Level ground everywhere, for tests without a hillshade to load
+    pub fn flat(extent: Rect) -> Dem {
+        Dem {
+            extent,
+            levels: Vec::new(),
+            shade: Shade {
+                width: 0,
+                height: 0,
+                values: Vec::new(),
+            },
+        }
+    }
+}
+
+fn read_shade(image: &Image) -> Option<Shade> {
+    let pixmap = image.peek_pixels()?;
+    let (width, height) = (image.width(), image.height());
+    let mut values = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let colour = pixmap.get_color((x, y));
+            values.push(((colour.r() as u32 + colour.g() as u32 + colour.b() as u32) / 3) as u8);
+        }
+    }
+    Some(Shade {
+        width,
+        height,
+        values,
+    })
+}
+
+// Returns the GDAL style geotransform and the CRS it is expressed in
+fn read_geo_transform(image_path: &str, width: i32, height: i32) -> Result<([f64; 6], String), Box<dyn Error>> {
+    let path = Path::new(image_path);
+
+    // World file next to the image (.pgw for PNG, or the generic .wld)
+    for extension in ["pgw", "wld"] {
+        let world_file = path.with_extension(extension);
+        if world_file.exists() {
+            let values = fs::read_to_string(&world_file)?.split_whitespace().map(|line| line.parse::<f64>()).collect::<Result<Vec<_>, _>>()?;
+            if values.len() != 6 {
+                return Err(format!("World file {} should have 6 lines", world_file.display()).into());
+            }
+
+            // World files are A, D, B, E, C, F and reference the centre of the top left pixel
+            let (a, d, b, e, c, f) = (values[0], values[1], values[2], values[3], values[4], values[5]);
+            return Ok(([c - a / 2.0 - b / 2.0, a, b, f - d / 2.0 - e / 2.0, d, e], DEFAULT_SOURCE_CRS.to_string()));
+        }
+    }
+
+    // Otherwise the GeoTIFF the image was exported from, or failing that the extent it has always had
+    let tiff = path.with_extension("tif");
+    let dataset = match Dataset::open(&tiff) {
+        Ok(dataset) => dataset,
+        Err(_) => {
+            let (pixel_width, pixel_height) = ((DEFAULT_EAST - DEFAULT_WEST) / width as f64, (DEFAULT_SOUTH - DEFAULT_NORTH) / height as f64);
+            return Ok(([DEFAULT_WEST, pixel_width, 0.0, DEFAULT_NORTH, 0.0, pixel_height], DEFAULT_SOURCE_CRS.to_string()));
+        }
+    };
+    let geo_transform = dataset.geo_transform()?;
+    let crs = match dataset.spatial_ref() {
+        Ok(srs) => format!("{}:{}", srs.auth_name()?, srs.auth_code()?),
+        Err(_) => DEFAULT_SOURCE_CRS.to_string(),
+    };
+    Ok((geo_transform, crs))
+}
+
+fn extent_to_world(geo_transform: &[f64; 6], width: i32, height: i32, source_crs: &str) -> Result<Rect, Box<dyn Error>> {
+    let west = geo_transform[0];
+    let north = geo_transform[3];
+    let east = west + geo_transform[1] * width as f64;
+    let south = north + geo_transform[5] * height as f64;
+
+    // Only need the projection once, at load
+    let top_left = project_to_bng(source_crs, west, north)?.to_world();
+    let bottom_right = project_to_bng(source_crs, east, south)?.to_world();
+    Ok(Rect::new(top_left.x, top_left.y, bottom_right.x, bottom_right.y))
+}
+
+fn downsample(image: &Image) -> Result<Image, Box<dyn Error>> {
+    let width = (image.width() / 2).max(1);
+    let height = (image.height() / 2).max(1);
+    let mut surface = surfaces::raster_n32_premul((width, height)).ok_or("Unable to create DEM surface")?;
+    let sampling = SamplingOptions::new(FilterMode::Linear, MipmapMode::None);
+    surface.canvas().draw_image_rect_with_sampling_options(image, None, Rect::from_xywh(0.0, 0.0, width as f32, height as f32), sampling, &Paint::default());
+    Ok(surface.image_snapshot())
+}
+
+fn tile_image(image: &Image, extent: &Rect) -> Vec<DemTile> {
+    let scale_x = extent.width() / image.width() as f32;
+    let scale_y = extent.height() / image.height() as f32;
+    let mut tiles = Vec::new();
+    for y in (0..image.height()).step_by(TILE_SIZE as usize) {
+        for x in (0..image.width()).step_by(TILE_SIZE as usize) {
+            let w = TILE_SIZE.min(image.width() - x);
+            let h = TILE_SIZE.min(image.height() - y);
+            let subset = match image.new_subset(IRect::from_xywh(x, y, w, h)) {
+                Some(subset) => subset,
+                None => continue,
+            };
+            let bounds = Rect::from_xywh(extent.left + x as f32 * scale_x, extent.top + y as f32 * scale_y, w as f32 * scale_x, h as f32 * scale_y);
+            tiles.push(DemTile {
+                bounds,
+                image: subset.with_default_mipmaps().unwrap_or(subset),
+            });
+        }
+    }
+    tiles
+}
+
+pub fn draw_dem(skia: &mut Skia, dem: &Dem) {
     let mut paint = Paint::default();
     paint.set_anti_alias(true);
     paint.set_style(Style::Fill);
 
-    // Rescale
-    let north = 60.655938;
-    let south = 49.908067;
-    let west = -9.222697;
-    let east = 1.559322; // 2.693491
-
-    // Define the transformation from OSGB 1936 (EPSG:27700) to Web Mercator (EPSG:3857)
-    let from_proj = "EPSG:4326";
-    let to_proj = "EPSG:27700";
-    let proj = Proj::new_known_crs(from_proj, to_proj, None).expect("Failed to create projection");
-    let c1 = proj.convert((west, north)).unwrap();
-    let c2 = proj.convert((east, south)).unwrap();
-    let dst = Rect::from_xywh(
-        0.0 + c1.x() / RATIO_ADJUST,
-        -c1.y() / RATIO_ADJUST,
-        (c2.x() - c1.x()) / RATIO_ADJUST,
-        -(c2.y() - c1.y()) / RATIO_ADJUST,
-    );
-
     let mut paint_shadow = Paint::default();
     paint_shadow.set_anti_alias(true);
     paint_shadow.set_style(Style::Fill);
     paint_shadow.set_color(Color::BLACK);
     paint_shadow.set_image_filter(skia.drop_shadow.clone());
 
-    // Draw "shadow"
-    skia.get_canvas().save();
+    let canvas = skia.get_canvas();
+    let visible = match canvas.local_clip_bounds() {
+        Some(visible) if visible.intersects(dem.extent) => visible,
+        _ => return,
+    };
+    let pixels_per_unit = canvas.total_matrix().scale_x();
+
+    // Draw "shadow", the coarsest level is plenty for a blur
+    canvas.save();
     let zz = 0.1;
-    skia.get_canvas().translate(Vector::new(zz, zz));
-    skia.get_canvas().draw_image_rect(image, None, dst, &paint_shadow);
-    skia.get_canvas().restore();
+    canvas.translate(Vector::new(zz, zz));
+    if let Some(coarsest) = dem.levels.last() {
+        coarsest.tiles.iter().for_each(|tile| {
+            canvas.draw_image_rect(&tile.image, None, tile.bounds, &paint_shadow);
+        });
+    }
+    canvas.restore();
 
-    // Draw DEM
+    // Draw DEM, only the tiles on screen
     let sampling = SamplingOptions::new(FilterMode::Linear, MipmapMode::Linear);
-    skia.get_canvas().draw_image_rect_with_sampling_options(image, None, dst, sampling, &paint);
+    let level = dem.level_for(pixels_per_unit);
+    level.tiles.iter().filter(|tile| tile.bounds.intersects(visible)).for_each(|tile| {
+        canvas.draw_image_rect_with_sampling_options(&tile.image, None, tile.bounds, sampling, &paint);
+    });
 }
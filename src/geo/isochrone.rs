@@ -0,0 +1,235 @@
+use crate::geo::crs::World;
+use crate::geo::roads::RoadGraph;
+use crate::gfx::camera::Camera;
+use crate::gfx::skia::Skia;
+use skia_safe::paint::Style;
+use skia_safe::utils::text_utils::Align;
+use skia_safe::{Color, Paint, Path, PathOp, Point};
+use std::collections::HashMap;
+
+// Minutes, unless others are given with --bands
+pub const DEFAULT_BANDS: [f64; 3] = [15.0, 30.0, 60.0];
+
+// Reachable nodes are binned into cells this size (km), the contours are traced through their centres
+const CELL_SIZE: f32 = 1.0;
+
+pub struct IsochroneBand {
+    pub minutes: f64,
+    pub path: Path,
+}
+
+pub struct Isochrone {
+    pub origin: World,
+    pub bands: Vec<IsochroneBand>,
+}
+
+// "10,20,40" as minutes, None if any of them isn't a positive number
+pub fn parse_bands(text: &str) -> Option<Vec<f64>> {
+    let bands: Vec<f64> = text.split(',').map(|band| band.trim().parse().ok().filter(|&minutes: &f64| minutes > 0.0)).collect::<Option<_>>()?;
+    (!bands.is_empty()).then_some(bands)
+}
+
+pub fn compute_isochrone(roads: &RoadGraph, origin: World, bands: &[f64]) -> Option<Isochrone> {
+    let start = roads.nearest_node(origin)?;
+    let max_minutes = bands.iter().cloned().fold(0.0, f64::max);
+    let reached = roads.drive_times(start, max_minutes * 60.0);
+
+    // Quickest time into each cell
+    let mut cells: HashMap<(i32, i32), f64> = HashMap::new();
+    for (node, time) in reached {
+        let p = roads.nodes[node].bng().to_world();
+        let cell = ((p.x / CELL_SIZE).floor() as i32, (p.y / CELL_SIZE).floor() as i32);
+        let entry = cells.entry(cell).or_insert(time);
+        *entry = entry.min(time);
+    }
+    let grid = TimeGrid::new(cells, max_minutes * 60.0);
+
+    // Each band is what's inside its contour but not the one before, so bands are rings rather than overlapping
+    let mut sorted = bands.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let contours: Vec<Path> = sorted.iter().map(|minutes| grid.contour(minutes * 60.0)).collect();
+    let paths = contours.iter().enumerate().map(|(i, contour)| match i {
+        0 => contour.clone(),
+        _ => contour.op(&contours[i - 1], PathOp::Difference).unwrap_or_else(|| contour.clone()),
+    });
+
+    Some(Isochrone {
+        origin,
+        bands: sorted
+            .into_iter()
+            .zip(paths)
+            .map(|(minutes, path)| IsochroneBand {
+                minutes,
+                path,
+            })
+            .collect(),
+    })
+}
+
+// Drive time at the centre of each cell, anything not reached counts as well beyond the last band
+struct TimeGrid {
+    cells: HashMap<(i32, i32), f64>,
+    unreached: f64,
+    min: (i32, i32),
+    max: (i32, i32),
+}
+
+// A cell side a contour crosses: the cell it starts from, and whether it runs along x or y
+type Crossing = (i32, i32, bool);
+
+impl TimeGrid {
+    fn new(cells: HashMap<(i32, i32), f64>, max_time: f64) -> TimeGrid {
+        let min = cells.keys().fold((i32::MAX, i32::MAX), |(x, y), &(cx, cy)| (x.min(cx), y.min(cy)));
+        let max = cells.keys().fold((i32::MIN, i32::MIN), |(x, y), &(cx, cy)| (x.max(cx), y.max(cy)));
+        TimeGrid {
+            cells,
+            unreached: max_time * 2.0 + 1.0,
+            min,
+            max,
+        }
+    }
+
+    fn time(&self, x: i32, y: i32) -> f64 {
+        self.cells.get(&(x, y)).copied().unwrap_or(self.unreached)
+    }
+
+    fn centre(x: i32, y: i32) -> Point {
+        Point::new((x as f32 + 0.5) * CELL_SIZE, (y as f32 + 0.5) * CELL_SIZE)
+    }
+
+    // Where the limit falls between the centres at each end of a crossing
+    fn crossing_point(&self, (x, y, along_x): Crossing, limit: f64) -> Point {
+        let (x2, y2) = if along_x {
+            (x + 1, y)
+        } else {
+            (x, y + 1)
+        };
+        let (a, b) = (self.time(x, y), self.time(x2, y2));
+        let t = ((limit - a) / (b - a)).clamp(0.0, 1.0) as f32;
+        let (p, q) = (Self::centre(x, y), Self::centre(x2, y2));
+        Point::new(p.x + (q.x - p.x) * t, p.y + (q.y - p.y) * t)
+    }
+
+    /// Marching squares over the cell centres, joined up into closed outlines of everywhere
+    /// reachable within limit seconds. Outlines wind the same way round, so holes stay empty.
+    fn contour(&self, limit: f64) -> Path {
+        let mut path = Path::new();
+        if self.cells.is_empty() {
+            return path;
+        }
+
+        // Each piece runs from where the contour enters a square to where it leaves, keyed by the entry
+        let mut pieces: HashMap<Crossing, Crossing> = HashMap::new();
+        for x in self.min.0 - 1..=self.max.0 {
+            for y in self.min.1 - 1..=self.max.1 {
+                let corners = [(x, y), (x + 1, y), (x + 1, y + 1), (x, y + 1)];
+                let sides: [Crossing; 4] = [(x, y, true), (x + 1, y, false), (x, y + 1, true), (x, y, false)];
+                let inside = corners.map(|(cx, cy)| self.time(cx, cy) <= limit);
+
+                // Walking round the corners, entries go from outside to inside and exits the other way
+                let mut entries = Vec::new();
+                let mut exits = Vec::new();
+                for i in 0..4 {
+                    match (inside[i], inside[(i + 1) % 4]) {
+                        (false, true) => entries.push(i),
+                        (true, false) => exits.push(i),
+                        _ => {}
+                    }
+                }
+                match (entries.as_slice(), exits.as_slice()) {
+                    (&[entry], &[exit]) => {
+                        pieces.insert(sides[entry], sides[exit]);
+                    }
+                    // A saddle, the average at the middle decides whether the inside corners join up
+                    (&[e1, e2], &[x1, x2]) => {
+                        let middle = corners.iter().map(|&(cx, cy)| self.time(cx, cy)).sum::<f64>() / 4.0;
+                        // Exits in the order they come walking on round from an entry
+                        let exits_from = |entry: usize| {
+                            if (x1 + 4 - entry) % 4 < (x2 + 4 - entry) % 4 {
+                                [x1, x2]
+                            } else {
+                                [x2, x1]
+                            }
+                        };
+                        for entry in [e1, e2] {
+                            let [next, previous] = exits_from(entry);
+                            let exit = if middle <= limit {
+                                previous
+                            } else {
+                                next
+                            };
+                            pieces.insert(sides[entry], sides[exit]);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        // Follow the pieces round until each outline closes
+        while let Some(&first) = pieces.keys().next() {
+            let mut outline = Vec::new();
+            let mut at = first;
+            while let Some(next) = pieces.remove(&at) {
+                outline.push(self.crossing_point(at, limit));
+                at = next;
+            }
+            if outline.len() > 2 {
+                path.add_poly(&outline, true);
+            }
+        }
+        path
+    }
+}
+
+// Green for the nearest band through yellow to red for the furthest, however many there are
+fn band_colour(index: usize, count: usize) -> Color {
+    let f = if count > 1 {
+        index as f32 / (count - 1) as f32
+    } else {
+        0.0
+    };
+    let lerp = |a: f32, b: f32, t: f32| (a + (b - a) * t) as u8;
+    let (r, g, b) = if f < 0.5 {
+        (lerp(0.0, 240.0, f * 2.0), lerp(150.0, 200.0, f * 2.0), lerp(60.0, 0.0, f * 2.0))
+    } else {
+        (lerp(240.0, 220.0, f * 2.0 - 1.0), lerp(200.0, 60.0, f * 2.0 - 1.0), lerp(0.0, 30.0, f * 2.0 - 1.0))
+    };
+    Color::from_argb(lerp(110.0, 70.0, f), r, g, b)
+}
+
+pub fn draw_isochrone(skia: &mut Skia, camera: &Camera, isochrone: &Isochrone) {
+    let zoom = camera.zoom;
+    let mut paint = Paint::default();
+    paint.set_anti_alias(true);
+    paint.set_style(Style::Fill);
+
+    let mut paint_origin = Paint::default();
+    paint_origin.set_anti_alias(true);
+    paint_origin.set_style(Style::Fill);
+    paint_origin.set_color(Color::BLACK);
+
+    let canvas = skia.get_canvas();
+    let count = isochrone.bands.len();
+    isochrone.bands.iter().enumerate().for_each(|(i, band)| {
+        paint.set_color(band_colour(i, count));
+        canvas.draw_path(&band.path, &paint);
+    });
+    canvas.draw_circle(isochrone.origin.to_point(), 5.0 / zoom, &paint_origin);
+}
+
+pub fn show_isochrone(skia: &mut Skia, isochrone: &Option<Isochrone>) {
+    let text = match isochrone {
+        Some(isochrone) => {
+            let bands: Vec<String> = isochrone.bands.iter().map(|band| format!("{}", band.minutes)).collect();
+            format!("Drive time: {} min", bands.join(" / "))
+        }
+        None => String::from("Drive time: pick a point or city"),
+    };
+
+    let mut paint = Paint::default();
+    paint.set_style(Style::StrokeAndFill);
+    paint.set_color(Color::BLACK);
+    let font = skia.font_main.clone();
+    skia.get_canvas().draw_text_align(text, Point::new(10.0, 70.0), &font, &paint, Align::Left);
+}
@@ -1,20 +1,20 @@
-use crate::geo::boundary::{create_boundaries, load_boundaries};
+use crate::geo::boundary::{create_boundaries, load_boundaries, load_land};
 use crate::geo::cities::load_cities_cbor_file;
 use crate::geo::data::{Geo, GeoWithPathAndCities};
+use crate::geo::dem::Dem;
+use crate::geo::roads::load_road_graph;
 use crate::geo::ways::{load_ways, categorise_ways, serialize_ways};
-use crate::gfx::skia::load_image_from_file;
 use geo::Geometry;
 use geojson::GeoJson;
 use std::error::Error;
 use std::fs::File;
 use std::io::BufReader;
 
-pub const RATIO_ADJUST: f32 = 1000.0;
-
 pub fn create_geo() {
     //load_geojson();
     //create_ways();
     //create_boundaries();
+    //create_road_graph();
     let ways = categorise_ways();
     serialize_ways(ways).expect("Unable to serialize Ways");
 }
@@ -65,13 +65,17 @@ pub fn load(radius: f64) -> Result<GeoWithPathAndCities, Box<dyn Error>> {
     let cities = load_cities_cbor_file("data/Cities.cbor", radius);
     let ways = load_ways();
     let boundaries = load_boundaries();
-    let image = load_image_from_file("data/hillshade.png");
+    let land = load_land();
+    let roads = load_road_graph();
+    let dem = Dem::load("data/hillshade.png")?;
 
     // Convert to Skia
     Ok(GeoWithPathAndCities {
         cities,
         ways,
-        dem: image,
+        dem,
         boundaries,
+        land,
+        roads,
     })
 }
@@ -0,0 +1,124 @@
+use crate::geo::crs::World;
+use crate::geo::data::{WayClass, WaySkia};
+use crate::geo::ways::snap_to_ways;
+use crate::gfx::camera::Camera;
+use crate::gfx::skia::Skia;
+use skia_safe::paint::Style;
+use skia_safe::utils::text_utils::Align;
+use skia_safe::{Color, Paint, Path, Point};
+use std::collections::HashMap;
+
+const METRES_PER_MILE: f64 = 1609.344;
+const SNAP_PIXELS: f32 = 10.0;
+
+#[derive(Default)]
+pub struct Measure {
+    pub vertices: Vec<World>,
+    pub closed: bool,
+    pub snap: bool,
+}
+
+impl Measure {
+    pub fn add_vertex(&mut self, point: World, ways: &HashMap<WayClass, Vec<WaySkia>>, camera: &Camera) {
+        let point = if self.snap {
+            snap_to_ways(ways, point.to_point(), camera.pixels(SNAP_PIXELS)).map(World::from_point).unwrap_or(point)
+        } else {
+            point
+        };
+        self.vertices.push(point);
+    }
+
+    pub fn undo(&mut self) {
+        self.vertices.pop();
+    }
+
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+        self.closed = false;
+    }
+
+    fn is_polygon(&self) -> bool {
+        self.closed && self.vertices.len() >= 3
+    }
+
+    // Everything is worked out in true BNG metres, not canvas units
+    pub fn length(&self) -> f64 {
+        let mut length = self.vertices.windows(2).map(|w| w[0].to_bng().distance(w[1].to_bng())).sum();
+        if self.is_polygon() {
+            length += self.vertices[self.vertices.len() - 1].to_bng().distance(self.vertices[0].to_bng());
+        }
+        length
+    }
+
+    pub fn area(&self) -> f64 {
+        if !self.is_polygon() {
+            return 0.0;
+        }
+
+        // Shoelace
+        let n = self.vertices.len();
+        let twice_area: f64 = (0..n)
+            .map(|i| {
+                let a = self.vertices[i].to_bng();
+                let b = self.vertices[(i + 1) % n].to_bng();
+                a.easting * b.northing - b.easting * a.northing
+            })
+            .sum();
+        twice_area.abs() / 2.0
+    }
+}
+
+pub fn draw_measure(skia: &mut Skia, camera: &Camera, measure: &Measure, cursor: World) {
+    if measure.vertices.is_empty() {
+        return;
+    }
+
+    // Keep the lines a constant width on screen
+    let zoom = camera.zoom;
+    let mut paint = Paint::default();
+    paint.set_anti_alias(true);
+    paint.set_style(Style::Stroke);
+    paint.set_color(Color::RED);
+    paint.set_stroke_width(2.0 / zoom);
+
+    let mut paint_fill = Paint::default();
+    paint_fill.set_anti_alias(true);
+    paint_fill.set_style(Style::Fill);
+    paint_fill.set_color(Color::from_argb(64, 255, 0, 0));
+
+    let points: Vec<Point> = measure.vertices.iter().map(|v| v.to_point()).collect();
+    let mut path = Path::new();
+    path.add_poly(&points, measure.is_polygon());
+
+    let canvas = skia.get_canvas();
+    if measure.is_polygon() {
+        canvas.draw_path(&path, &paint_fill);
+    } else {
+        // Rubber band to the cursor
+        canvas.draw_line(points[points.len() - 1], cursor.to_point(), &paint);
+    }
+    canvas.draw_path(&path, &paint);
+
+    paint.set_style(Style::Fill);
+    points.iter().for_each(|p| {
+        canvas.draw_circle(*p, 4.0 / zoom, &paint);
+    });
+}
+
+pub fn show_measurement(skia: &mut Skia, measure: &Measure) {
+    let length = measure.length();
+    let mut text = format!("Length: {:.2} km ({:.2} mi)", length / 1000.0, length / METRES_PER_MILE);
+    if measure.is_polygon() {
+        let area = measure.area();
+        text += &format!("  Area: {:.2} km² ({:.2} sq mi)", area / 1.0e6, area / (METRES_PER_MILE * METRES_PER_MILE));
+    }
+    if measure.snap {
+        text += "  [Snap]";
+    }
+
+    let mut paint = Paint::default();
+    paint.set_style(Style::StrokeAndFill);
+    paint.set_color(Color::BLACK);
+    let font = skia.font_main.clone();
+    skia.get_canvas().draw_text_align(text, Point::new(10.0, 70.0), &font, &paint, Align::Left);
+}
@@ -1,6 +1,12 @@
 pub mod cities;
+pub mod crs;
 pub mod data;
 pub mod dem;
+pub mod isochrone;
 pub mod load;
+pub mod measure;
+pub mod roads;
+pub mod route;
+pub mod territory;
 pub mod boundary;
 pub mod ways;
@@ -0,0 +1,356 @@
+use crate::geo::crs::{Bng, World};
+use crate::geo::data::{WayClass, WayForm, WayPoint};
+use crate::geo::ways::{get_geometry, parse_way_class, parse_way_form};
+use gdal::vector::LayerAccess;
+use gdal::Dataset;
+use serde::{Deserialize, Serialize};
+use serde_cbor::from_reader;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::fs::File;
+use std::io::BufReader;
+
+// Grid cell for nearest node lookups, in scaled units (km)
+const NODE_CELL_SIZE: f64 = 1.0;
+
+pub type NodeId = usize;
+pub type EdgeId = usize;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RoadNode {
+    pub x: f64,
+    pub y: f64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RoadEdge {
+    pub from: NodeId,
+    pub to: NodeId,
+    pub length: f32,
+    pub class: WayClass,
+    pub form: WayForm,
+    pub way_points: Vec<WayPoint>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct RoadGraph {
+    pub nodes: Vec<RoadNode>,
+    pub edges: Vec<RoadEdge>,
+    #[serde(skip)]
+    pub adjacency: Vec<Vec<EdgeId>>,
+    #[serde(skip)]
+    node_grid: HashMap<(i32, i32), Vec<NodeId>>,
+}
+
+pub struct Route {
+    pub nodes: Vec<NodeId>,
+    pub edges: Vec<EdgeId>,
+    pub length: f64,
+    pub time: f64,
+}
+
+impl RoadNode {
+    pub fn bng(&self) -> Bng {
+        Bng::from_scaled(self.x, self.y)
+    }
+}
+
+impl RoadEdge {
+    // Seconds to drive the link, infinite if it can't be driven at all
+    pub fn time(&self) -> f64 {
+        let kph = self.class.speed_kph() * self.form.speed_factor();
+        if kph <= 0.0 {
+            return f64::INFINITY;
+        }
+        self.length as f64 / (kph as f64 / 3.6)
+    }
+
+    pub fn other_end(&self, node: NodeId) -> NodeId {
+        if self.from == node {
+            self.to
+        } else {
+            self.from
+        }
+    }
+
+    // Geometry in the direction of travel, starting at node
+    pub fn points_from(&self, node: NodeId) -> Vec<World> {
+        let mut points: Vec<World> = self.way_points.iter().map(|wp| Bng::from_scaled(wp.x, wp.y).to_world()).collect();
+        if self.from != node {
+            points.reverse();
+        }
+        points
+    }
+}
+
+// Popped in order of priority, cost is what it took to get here and is compared exactly
+#[derive(PartialEq)]
+struct QueueEntry {
+    priority: f64,
+    cost: f64,
+    node: NodeId,
+}
+
+impl Eq for QueueEntry {}
+
+impl Ord for QueueEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed so the BinaryHeap pops the cheapest first
+        other.priority.total_cmp(&self.priority).then_with(|| self.node.cmp(&other.node))
+    }
+}
+
+impl PartialOrd for QueueEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Cheapest cost found to each node and the link it was reached by, plus the nodes in the order they were settled
+struct Search {
+    best: Vec<f64>,
+    came_from: Vec<Option<EdgeId>>,
+    reached: Vec<(NodeId, f64)>,
+}
+
+impl RoadGraph {
+    pub fn build_index(&mut self) {
+        self.adjacency = vec![Vec::new(); self.nodes.len()];
+        for (id, edge) in self.edges.iter().enumerate() {
+            self.adjacency[edge.from].push(id);
+            if edge.to != edge.from {
+                self.adjacency[edge.to].push(id);
+            }
+        }
+
+        self.node_grid.clear();
+        for (id, node) in self.nodes.iter().enumerate() {
+            self.node_grid.entry(grid_cell(node.x, node.y)).or_default().push(id);
+        }
+    }
+
+    pub fn nearest_node(&self, point: World) -> Option<NodeId> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let (x, y) = point.to_bng().to_scaled();
+        let (cx, cy) = grid_cell(x, y);
+
+        // Search outwards a ring at a time. Anything beyond ring r is at least r cells away,
+        // so stop once the best so far is nearer than that
+        let mut best: Option<(NodeId, f64)> = None;
+        for ring in 0..1000 {
+            for gx in cx - ring..=cx + ring {
+                for gy in cy - ring..=cy + ring {
+                    if (gx - cx).abs() != ring && (gy - cy).abs() != ring {
+                        continue;
+                    }
+                    if let Some(ids) = self.node_grid.get(&(gx, gy)) {
+                        for &id in ids {
+                            let node = &self.nodes[id];
+                            let d = (node.x - x).powi(2) + (node.y - y).powi(2);
+                            if best.is_none_or(|(_, best_d)| d < best_d) {
+                                best = Some((id, d));
+                            }
+                        }
+                    }
+                }
+            }
+            let beyond = ring as f64 * NODE_CELL_SIZE;
+            if best.is_some_and(|(_, d)| d <= beyond * beyond) {
+                break;
+            }
+        }
+        best.map(|(id, _)| id)
+    }
+
+    /// A* on drive time, using straight line distance at motorway speed as the heuristic.
+    pub fn shortest_path(&self, start: NodeId, end: NodeId) -> Option<Route> {
+        let max_speed = WayClass::Motorway.speed_kph() as f64 / 3.6;
+        let goal = self.nodes[end].bng();
+        let heuristic = |node: NodeId| self.nodes[node].bng().distance(goal) / max_speed;
+        let search = self.search(&[start], Some(end), f64::INFINITY, RoadEdge::time, |_| true, heuristic);
+        if search.best[end].is_infinite() {
+            return None;
+        }
+
+        // Walk back from the end
+        let mut nodes = vec![end];
+        let mut edges = Vec::new();
+        let mut node = end;
+        while let Some(edge_id) = search.came_from[node] {
+            edges.push(edge_id);
+            node = self.edges[edge_id].other_end(node);
+            nodes.push(node);
+        }
+        nodes.reverse();
+        edges.reverse();
+
+        Some(Route {
+            length: edges.iter().map(|&e| self.edges[e].length as f64).sum(),
+            time: search.best[end],
+            nodes,
+            edges,
+        })
+    }
+
+    /// Dijkstra out from start, returning every node reachable within max_time seconds.
+    pub fn drive_times(&self, start: NodeId, max_time: f64) -> Vec<(NodeId, f64)> {
+        self.search(&[start], None, max_time, RoadEdge::time, |_| true, |_| 0.0).reached
+    }
+
+    /// Dijkstra out from every start at once, with each link costed by the caller. Nodes that
+    /// aren't passable are never entered. Returns every node within max_cost of the nearest start.
+    pub fn spread_from(&self, starts: &[NodeId], max_cost: f64, cost: impl Fn(&RoadEdge) -> f64, passable: impl Fn(NodeId) -> bool) -> Vec<(NodeId, f64)> {
+        self.search(starts, None, max_cost, cost, passable, |_| 0.0).reached
+    }
+
+    // The search behind all of the above: A* when given a goal and a heuristic, stopping once the goal
+    // is reached, otherwise Dijkstra over everything within max_cost
+    fn search(
+        &self,
+        starts: &[NodeId],
+        goal: Option<NodeId>,
+        max_cost: f64,
+        cost: impl Fn(&RoadEdge) -> f64,
+        passable: impl Fn(NodeId) -> bool,
+        heuristic: impl Fn(NodeId) -> f64,
+    ) -> Search {
+        let mut best = vec![f64::INFINITY; self.nodes.len()];
+        let mut came_from = vec![None; self.nodes.len()];
+        let mut reached = Vec::new();
+        let mut queue = BinaryHeap::new();
+        for &start in starts.iter().filter(|&&start| passable(start)) {
+            best[start] = 0.0;
+            queue.push(QueueEntry {
+                priority: heuristic(start),
+                cost: 0.0,
+                node: start,
+            });
+        }
+
+        while let Some(QueueEntry {
+            cost: so_far,
+            node,
+            ..
+        }) = queue.pop()
+        {
+            if so_far > best[node] {
+                continue;
+            }
+            reached.push((node, so_far));
+            if Some(node) == goal {
+                break;
+            }
+            for &edge_id in &self.adjacency[node] {
+                let edge = &self.edges[edge_id];
+                let next = edge.other_end(node);
+                let total = so_far + cost(edge);
+                if total <= max_cost && total < best[next] && passable(next) {
+                    best[next] = total;
+                    came_from[next] = Some(edge_id);
+                    queue.push(QueueEntry {
+                        priority: total + heuristic(next),
+                        cost: total,
+                        node: next,
+                    });
+                }
+            }
+        }
+        Search {
+            best,
+            came_from,
+            reached,
+        }
+    }
+
+    pub fn route_points(&self, route: &Route) -> Vec<World> {
+        let mut points = Vec::new();
+        route.edges.iter().zip(route.nodes.iter()).for_each(|(&edge, &node)| {
+            points.extend(self.edges[edge].points_from(node));
+        });
+        points
+    }
+}
+
+fn grid_cell(x: f64, y: f64) -> (i32, i32) {
+    ((x / NODE_CELL_SIZE).floor() as i32, (y / NODE_CELL_SIZE).floor() as i32)
+}
+
+pub fn create_road_graph() {
+    let dataset = Dataset::open("/Users/daryl/OSM/oproad_gpkg_gb/Data/oproad_gb.gpkg").unwrap();
+
+    // Nodes first so links can refer to them by index
+    let mut road_node = dataset.layer_by_name("road_node").unwrap();
+    let mut graph = RoadGraph::default();
+    let mut node_ids = HashMap::new();
+    for feature in road_node.features() {
+        let id = feature.field_as_string_by_name("id").unwrap().unwrap_or_default();
+        let (x, y, _) = feature.geometry().unwrap().get_point(0);
+        let (x, y) = Bng::new(x, y).to_scaled();
+        node_ids.insert(id, graph.nodes.len());
+        graph.nodes.push(RoadNode {
+            x,
+            y,
+        });
+    }
+    println!("There are {} road nodes", graph.nodes.len());
+
+    let mut road_link = dataset.layer_by_name("road_link").unwrap();
+    for feature in road_link.features() {
+        let extract_string = |field: &str| feature.field_as_string_by_name(field).unwrap_or_default().unwrap_or_default();
+        let from = node_ids.get(&extract_string("start_node"));
+        let to = node_ids.get(&extract_string("end_node"));
+        let (from, to) = match (from, to) {
+            (Some(from), Some(to)) => (*from, *to),
+            _ => continue,
+        };
+
+        graph.edges.push(RoadEdge {
+            from,
+            to,
+            length: feature.field_as_double_by_name("length").unwrap_or_default().unwrap_or_default() as f32,
+            class: parse_way_class(&extract_string("road_classification")),
+            form: parse_way_form(&extract_string("form_of_way")),
+            way_points: get_geometry(feature.geometry().unwrap(), false),
+        });
+    }
+    println!("There are {} road links", graph.edges.len());
+
+    // Serialise
+    let file = File::create("data/RoadGraph.cbor").unwrap();
+    let writer = std::io::BufWriter::new(file);
+    serde_cbor::to_writer(writer, &graph).unwrap();
+}
+
+pub fn load_road_graph() -> RoadGraph {
+    let file = File::open("data/RoadGraph.cbor").expect("Unable to open road graph file");
+    let reader = BufReader::new(file);
+    let mut graph: RoadGraph = from_reader(reader).expect("Unable to read road graph file");
+    graph.build_index();
+
+    println!("There are {} road nodes and {} road links", graph.nodes.len(), graph.edges.len());
+    graph
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nearest_node_looks_past_the_first_ring_it_finds_one_in() {
+        // The first found is in the next cell diagonally, the nearest three cells across
+        let mut roads = RoadGraph {
+            nodes: [(1.99, 1.99), (-2.05, 0.5)]
+                .iter()
+                .map(|&(x, y)| RoadNode {
+                    x,
+                    y,
+                })
+                .collect(),
+            ..RoadGraph::default()
+        };
+        roads.build_index();
+        assert_eq!(roads.nearest_node(World::new(0.01, -0.5)), Some(1));
+    }
+}
@@ -0,0 +1,79 @@
+use crate::geo::crs::World;
+use crate::geo::roads::{NodeId, RoadGraph, Route};
+use crate::gfx::camera::Camera;
+use crate::gfx::skia::Skia;
+use skia_safe::paint::{Cap, Join, Style};
+use skia_safe::utils::text_utils::Align;
+use skia_safe::{Color, Paint, Path, Point};
+
+#[derive(Default)]
+pub struct RoutePlanner {
+    pub start: Option<NodeId>,
+    pub end: Option<NodeId>,
+    pub route: Option<Route>,
+    pub path: Path,
+}
+
+impl RoutePlanner {
+    // First click picks the start, the second the end, a third starts again
+    pub fn pick(&mut self, roads: &RoadGraph, point: World) {
+        let node = roads.nearest_node(point);
+        if self.start.is_none() || self.end.is_some() {
+            self.clear();
+            self.start = node;
+            return;
+        }
+        self.end = node;
+        if let (Some(start), Some(end)) = (self.start, self.end) {
+            self.route = roads.shortest_path(start, end);
+            self.path = Path::new();
+            if let Some(route) = &self.route {
+                let points: Vec<Point> = roads.route_points(route).iter().map(|p| p.to_point()).collect();
+                self.path.add_poly(&points, false);
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = RoutePlanner::default();
+    }
+}
+
+pub fn draw_route(skia: &mut Skia, camera: &Camera, planner: &RoutePlanner, roads: &RoadGraph) {
+    let zoom = camera.zoom;
+    let mut paint = Paint::default();
+    paint.set_anti_alias(true);
+    paint.set_style(Style::Stroke);
+    paint.set_stroke_cap(Cap::Round);
+    paint.set_stroke_join(Join::Round);
+    paint.set_color(Color::from_argb(200, 220, 20, 60));
+    paint.set_stroke_width(5.0 / zoom);
+
+    let canvas = skia.get_canvas();
+    canvas.draw_path(&planner.path, &paint);
+
+    // End points
+    paint.set_style(Style::Fill);
+    [planner.start, planner.end].iter().flatten().for_each(|&node| {
+        let p = roads.nodes[node].bng().to_world().to_point();
+        canvas.draw_circle(p, 6.0 / zoom, &paint);
+    });
+}
+
+pub fn show_route(skia: &mut Skia, planner: &RoutePlanner) {
+    let text = match (&planner.route, planner.start, planner.end) {
+        (Some(route), _, _) => {
+            let minutes = (route.time / 60.0).round() as i64;
+            format!("Route: {:.1} km, {}h {:02}m", route.length / 1000.0, minutes / 60, minutes % 60)
+        }
+        (None, Some(_), Some(_)) => String::from("Route: no path"),
+        (None, Some(_), None) => String::from("Route: pick destination"),
+        _ => String::from("Route: pick start"),
+    };
+
+    let mut paint = Paint::default();
+    paint.set_style(Style::StrokeAndFill);
+    paint.set_color(Color::BLACK);
+    let font = skia.font_main.clone();
+    skia.get_canvas().draw_text_align(text, Point::new(10.0, 70.0), &font, &paint, Align::Left);
+}
@@ -0,0 +1,130 @@
+use crate::game::player::{Player, PlayerId};
+use crate::geo::crs::{Bng, World};
+use crate::geo::data::Location;
+use crate::gfx::camera::Camera;
+use crate::gfx::skia::Skia;
+use skia_safe::paint::Style;
+use skia_safe::{Paint, Path, PathOp, Point, Rect};
+use std::rc::Rc;
+
+// Cells are built inside the land bounds grown by this much (km)
+const BOUNDS_MARGIN: f32 = 20.0;
+
+pub struct Territories {
+    // Voronoi cell for each city, indexed by CityId
+    cells: Vec<Vec<World>>,
+    pub regions: Vec<(PlayerId, Path)>,
+}
+
+impl Territories {
+    pub fn new(cities: &[Rc<Location>], land: &Path) -> Territories {
+        let sites: Vec<World> = cities.iter().map(|city| Bng::from_scaled(city.x, city.y).to_world()).collect();
+        let mut bounds = *land.bounds();
+        if land.is_empty() {
+            let points: Vec<Point> = sites.iter().map(|site| site.to_point()).collect();
+            bounds.set_bounds(&points);
+        }
+        let bounds = bounds.with_outset((BOUNDS_MARGIN, BOUNDS_MARGIN));
+
+        Territories {
+            cells: (0..sites.len()).map(|i| voronoi_cell(i, &sites, bounds)).collect(),
+            regions: Vec::new(),
+        }
+    }
+
+    // Merge each player's cells into one region, call whenever ownership changes
+    pub fn update(&mut self, players: &[Player]) {
+        self.regions = players
+            .iter()
+            .enumerate()
+            .filter(|(_, player)| player.takes_turns())
+            .map(|(id, player)| {
+                let region = player.cities.iter().fold(Path::new(), |region, &city| {
+                    let mut cell = Path::new();
+                    let points: Vec<Point> = self.cells[city].iter().map(|p| p.to_point()).collect();
+                    cell.add_poly(&points, true);
+                    region.op(&cell, PathOp::Union).unwrap_or(region)
+                });
+                (id, region)
+            })
+            .collect();
+    }
+}
+
+// Bounds cut down by the half plane nearer this site than each other site
+fn voronoi_cell(index: usize, sites: &[World], bounds: Rect) -> Vec<World> {
+    let site = sites[index];
+    let mut cell = vec![
+        World::new(bounds.left, bounds.top),
+        World::new(bounds.right, bounds.top),
+        World::new(bounds.right, bounds.bottom),
+        World::new(bounds.left, bounds.bottom),
+    ];
+
+    // Nearest first, so the cell shrinks quickly and we can stop once no other site can cut it
+    let mut others: Vec<(f32, World)> = sites.iter().enumerate().filter(|(i, _)| *i != index).map(|(_, other)| (distance(site, *other), *other)).collect();
+    others.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    for (d, other) in others {
+        let reach = cell.iter().map(|p| distance(site, *p)).fold(0.0, f32::max);
+        if d / 2.0 > reach {
+            break;
+        }
+        cell = clip_to_half_plane(&cell, site, other);
+        if cell.is_empty() {
+            break;
+        }
+    }
+    cell
+}
+
+// Sutherland-Hodgman against the bisector of site and other, keeping the site side
+fn clip_to_half_plane(polygon: &[World], site: World, other: World) -> Vec<World> {
+    let (nx, ny) = (other.x - site.x, other.y - site.y);
+    let (mx, my) = ((site.x + other.x) / 2.0, (site.y + other.y) / 2.0);
+    let side = |p: World| (p.x - mx) * nx + (p.y - my) * ny;
+
+    let mut out = Vec::with_capacity(polygon.len() + 1);
+    for (i, &current) in polygon.iter().enumerate() {
+        let next = polygon[(i + 1) % polygon.len()];
+        let (sc, sn) = (side(current), side(next));
+        if sc <= 0.0 {
+            out.push(current);
+        }
+        if (sc <= 0.0) != (sn <= 0.0) {
+            let t = sc / (sc - sn);
+            out.push(World::new(current.x + (next.x - current.x) * t, current.y + (next.y - current.y) * t));
+        }
+    }
+    out
+}
+
+fn distance(a: World, b: World) -> f32 {
+    ((a.x - b.x).powi(2) + (a.y - b.y).powi(2)).sqrt()
+}
+
+pub fn draw_territories(skia: &mut Skia, camera: &Camera, territories: &Territories, players: &[Player], land: &Path) {
+    let zoom = camera.zoom;
+    let mut paint_fill = Paint::default();
+    paint_fill.set_anti_alias(true);
+    paint_fill.set_style(Style::Fill);
+
+    let mut paint_border = Paint::default();
+    paint_border.set_anti_alias(true);
+    paint_border.set_style(Style::Stroke);
+    paint_border.set_stroke_width(2.0 / zoom);
+
+    let canvas = skia.get_canvas();
+    canvas.save();
+    if !land.is_empty() {
+        canvas.clip_path(land, None, true);
+    }
+    territories.regions.iter().for_each(|(owner, region)| {
+        let colour = players[*owner].colour;
+        paint_fill.set_color(colour.with_a(50));
+        paint_border.set_color(colour.with_a(180));
+        canvas.draw_path(region, &paint_fill);
+        canvas.draw_path(region, &paint_border);
+    });
+    canvas.restore();
+}
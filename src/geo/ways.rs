@@ -1,12 +1,12 @@
+use crate::geo::crs::Bng;
 use crate::geo::data::{Way, WayClass, WayForm, WayPoint, WaySkia};
-use crate::geo::load::RATIO_ADJUST;
 use crate::gfx::skia::Skia;
 use gdal::vector::LayerAccess;
 use gdal::Dataset;
 use geos::Geometry;
 use serde_cbor::from_reader;
 use skia_safe::paint::Style;
-use skia_safe::{scalar, Color, Paint, Path, Point};
+use skia_safe::{Color, Paint, Path, Point, Rect};
 use std::collections::HashMap;
 use std::error::Error;
 use std::fs::File;
@@ -42,7 +42,7 @@ pub fn load_ways() -> HashMap<WayClass, Vec<WaySkia>> {
 pub fn path_from_ways(points: &Vec<WayPoint>) -> Path {
     let mut p = Path::new();
     points.iter().for_each(|wp| {
-        let cpp = Point::new(wp.x as scalar, -wp.y as scalar);
+        let cpp = Bng::from_scaled(wp.x, wp.y).to_world().to_point();
         if wp.is_start {
             p.move_to(cpp);
         } else {
@@ -87,27 +87,8 @@ pub fn create_ways() {
         let form_of_way = extract_string("form_of_way");
         let name = extract_string("name_1");
 
-        let clazz = match road_classification.as_str() {
-            "A Road" => WayClass::ARoad,
-            "B Road" => WayClass::BRoad,
-            "Motorway" => WayClass::Motorway,
-            "Unclassified" => WayClass::Unclassified,
-            "Not Classified" => WayClass::Unclassified,
-            "Classified Unnumbered" => WayClass::Unclassified,
-            "Unknown" => WayClass::Unknown,
-            _ => todo!("Unknown class: {}", road_classification),
-        };
-
-        let form = match form_of_way.as_str() {
-            "Single Carriageway" => WayForm::SingleCarriageway,
-            "Shared Use Carriageway" => WayForm::SingleCarriageway,
-            "Dual Carriageway" => WayForm::DualCarriageway,
-            "Collapsed Dual Carriageway" => WayForm::CollapsedDualCarriageway,
-            "Slip Road" => WayForm::SlipRoad,
-            "Roundabout" => WayForm::Roundabout,
-            "Guided Busway" => WayForm::PublicTransportWay,
-            _ => todo!("Unknown form: {}", form_of_way),
-        };
+        let clazz = parse_way_class(&road_classification);
+        let form = parse_way_form(&form_of_way);
 
         // Geometry
         let geometry = feature.geometry().unwrap();
@@ -140,6 +121,32 @@ pub fn create_ways() {
     serde_cbor::to_writer(writer, &waypoints_concat).unwrap();
 }
 
+pub fn parse_way_class(road_classification: &str) -> WayClass {
+    match road_classification {
+        "A Road" => WayClass::ARoad,
+        "B Road" => WayClass::BRoad,
+        "Motorway" => WayClass::Motorway,
+        "Unclassified" => WayClass::Unclassified,
+        "Not Classified" => WayClass::Unclassified,
+        "Classified Unnumbered" => WayClass::Unclassified,
+        "Unknown" => WayClass::Unknown,
+        _ => todo!("Unknown class: {}", road_classification),
+    }
+}
+
+pub fn parse_way_form(form_of_way: &str) -> WayForm {
+    match form_of_way {
+        "Single Carriageway" => WayForm::SingleCarriageway,
+        "Shared Use Carriageway" => WayForm::SingleCarriageway,
+        "Dual Carriageway" => WayForm::DualCarriageway,
+        "Collapsed Dual Carriageway" => WayForm::CollapsedDualCarriageway,
+        "Slip Road" => WayForm::SlipRoad,
+        "Roundabout" => WayForm::Roundabout,
+        "Guided Busway" => WayForm::PublicTransportWay,
+        _ => todo!("Unknown form: {}", form_of_way),
+    }
+}
+
 pub fn get_geometry(geometry: &gdal::vector::Geometry, simplify: bool) -> Vec<WayPoint> {
     let mut my = Vec::new();
     if simplify {
@@ -147,10 +154,11 @@ pub fn get_geometry(geometry: &gdal::vector::Geometry, simplify: bool) -> Vec<Wa
     }
     for i in 0..geometry.point_count() {
         let (x, y, _) = geometry.get_point(i as i32);
+        let (x, y) = Bng::new(x, y).to_scaled();
         my.push(WayPoint {
             is_start: i == 0,
-            x: x / RATIO_ADJUST as f64,
-            y: y / RATIO_ADJUST as f64,
+            x,
+            y,
         });
     }
     my
@@ -188,6 +196,37 @@ pub fn draw_ways(skia: &mut Skia, ways: &HashMap<WayClass, Vec<WaySkia>>) {
     draw_ways_type(skia, ways.get(&WayClass::Motorway).unwrap());
 }
 
+// Nearest point on any drawn road within radius, checking path bounds first so it's quick enough for a click
+pub fn snap_to_ways(ways: &HashMap<WayClass, Vec<WaySkia>>, point: Point, radius: f32) -> Option<Point> {
+    let search = Rect::new(point.x - radius, point.y - radius, point.x + radius, point.y + radius);
+    let mut best = None;
+    let mut best_distance = radius;
+    ways.iter().filter(|(class, _)| **class != WayClass::Unknown).for_each(|(_, ways)| {
+        ways.iter().filter(|w| w.path.bounds().intersects(search)).for_each(|w| {
+            for i in 1..w.path.count_points() {
+                let (a, b) = (w.path.get_point(i - 1).unwrap(), w.path.get_point(i).unwrap());
+                let candidate = closest_point_on_segment(point, a, b);
+                let distance = Point::distance(point, candidate);
+                if distance < best_distance {
+                    best_distance = distance;
+                    best = Some(candidate);
+                }
+            }
+        });
+    });
+    best
+}
+
+fn closest_point_on_segment(p: Point, a: Point, b: Point) -> Point {
+    let ab = b - a;
+    let length_squared = ab.x * ab.x + ab.y * ab.y;
+    if length_squared == 0.0 {
+        return a;
+    }
+    let t = (((p.x - a.x) * ab.x + (p.y - a.y) * ab.y) / length_squared).clamp(0.0, 1.0);
+    Point::new(a.x + ab.x * t, a.y + ab.y * t)
+}
+
 fn draw_ways_type(skia: &mut Skia, ways: &[WaySkia]) {
     let anti_alias = true;
     
@@ -0,0 +1,171 @@
+use crate::events::{Event, EventBus};
+use crate::geo::crs::{Screen, World, GRID_HEIGHT, GRID_WIDTH, RATIO_ADJUST};
+use skia_safe::Vector;
+
+pub const MIN_ZOOM: f32 = 0.7;
+pub const MAX_ZOOM: f32 = 100.0;
+
+/// Where the map is looked at from: the world point in the middle of the window and how far
+/// in. Knows nothing of the GPU, drawing only reads it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    pub target: World,
+    // Pixels per kilometre
+    pub zoom: f32,
+    pub zoom_min: f32,
+    pub zoom_max: f32,
+    // Middle of the window, in screen pixels
+    pub centre: Vector,
+}
+
+impl Camera {
+    pub fn new(centre: Vector) -> Camera {
+        Camera {
+            target: World::new(400.0, -525.0),
+            zoom: MIN_ZOOM,
+            zoom_min: MIN_ZOOM,
+            zoom_max: MAX_ZOOM,
+            centre,
+        }
+    }
+
+    pub fn to_world(self, screen: Screen) -> World {
+        screen.to_world(self.centre, self.zoom, self.target.to_point())
+    }
+
+    pub fn to_screen(self, world: World) -> Screen {
+        world.to_screen(self.centre, self.zoom, self.target.to_point())
+    }
+
+    // Canvas units covering so many screen pixels, for picking and line widths
+    pub fn pixels(&self, pixels: f32) -> f32 {
+        pixels / self.zoom
+    }
+
+    // Whether anything within margin pixels of point would show
+    pub fn is_visible(&self, point: World, margin: f32) -> bool {
+        let screen = self.to_screen(point);
+        let (width, height) = (self.centre.x * 2.0, self.centre.y * 2.0);
+        screen.x >= -margin && screen.x <= width + margin && screen.y >= -margin && screen.y <= height + margin
+    }
+
+    // Moves by screen pixels, the map follows the pointer
+    pub fn pan(&mut self, dx: f32, dy: f32) {
+        self.target.x -= dx / self.zoom;
+        self.target.y -= dy / self.zoom;
+        self.clamp();
+    }
+
+    pub fn zoom_by(&mut self, delta: f32) {
+        self.zoom += delta;
+        self.clamp();
+    }
+
+    pub fn scale(&mut self, factor: f32) {
+        self.zoom *= factor;
+        self.clamp();
+    }
+
+    // Close in on something, halfway to the nearest zoom
+    pub fn focus(&mut self, target: World) {
+        self.set(target, self.zoom_max / 2.0);
+    }
+
+    pub fn set(&mut self, target: World, zoom: f32) {
+        self.target = target;
+        self.zoom = zoom;
+        self.clamp();
+    }
+
+    // Zoom within its limits and the middle of the window over the national grid
+    fn clamp(&mut self) {
+        self.zoom = self.zoom.clamp(self.zoom_min, self.zoom_max);
+        let width = (GRID_WIDTH / RATIO_ADJUST as f64) as f32;
+        let height = (GRID_HEIGHT / RATIO_ADJUST as f64) as f32;
+        self.target.x = self.target.x.clamp(0.0, width);
+        self.target.y = self.target.y.clamp(-height, 0.0);
+    }
+
+    // The camera only changes here, in response to events, and says so when it does
+    pub fn handle_event(&mut self, event: &Event, bus: &mut EventBus) {
+        match *event {
+            Event::CameraPanned {
+                dx,
+                dy,
+            } => self.pan(dx, dy),
+            Event::CameraZoomed {
+                delta,
+            } => self.zoom_by(delta),
+            Event::CameraScaled {
+                factor,
+            } => self.scale(factor),
+            Event::CameraFocused {
+                target,
+            } => self.focus(target),
+            Event::CameraSet {
+                target,
+                zoom,
+            } => self.set(target, zoom),
+            _ => return,
+        }
+        bus.push(Event::CameraMoved {
+            camera: *self,
+        });
+    }
+}
+
+// Until the camera first reports where it is, or for games without a window
+impl Default for Camera {
+    fn default() -> Camera {
+        Camera::new(Vector::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera() -> Camera {
+        let mut camera = Camera::new(Vector::new(400.0, 300.0));
+        camera.set(World::new(350.0, -500.0), 4.0);
+        camera
+    }
+
+    #[test]
+    fn screen_and_world_points_convert_back_and_forth() {
+        let camera = camera();
+        for (x, y) in [(0.0, 0.0), (400.0, 300.0), (799.0, 12.5), (-40.0, 650.0)] {
+            let screen = camera.to_screen(camera.to_world(Screen::new(x, y)));
+            assert!((screen.x - x).abs() < 1e-3 && (screen.y - y).abs() < 1e-3, "({}, {}) came back as {:?}", x, y, screen);
+        }
+        assert_eq!(camera.to_screen(camera.target), Screen::new(400.0, 300.0));
+    }
+
+    #[test]
+    fn the_camera_stays_over_the_grid_and_within_its_zoom() {
+        let mut camera = camera();
+        camera.set(World::new(-50.0, 20.0), 1000.0);
+        assert_eq!((camera.target, camera.zoom), (World::new(0.0, 0.0), MAX_ZOOM));
+        camera.set(World::new(900.0, -2000.0), 0.01);
+        assert_eq!((camera.target, camera.zoom), (World::new(700.0, -1300.0), MIN_ZOOM));
+        camera.pan(1.0e6, -1.0e6);
+        assert_eq!(camera.target, World::new(0.0, 0.0));
+        camera.zoom_by(-10.0);
+        assert_eq!(camera.zoom, MIN_ZOOM);
+        camera.scale(1000.0);
+        assert_eq!(camera.zoom, MAX_ZOOM);
+    }
+
+    #[test]
+    fn points_are_visible_to_the_window_edges_and_margin() {
+        let camera = camera();
+        let at = |x: f32, y: f32| camera.to_world(Screen::new(x, y));
+        assert!(camera.is_visible(at(0.0, 0.0), 0.0));
+        assert!(camera.is_visible(at(800.0, 600.0), 0.0));
+        assert!(!camera.is_visible(at(-10.0, 300.0), 0.0));
+        assert!(!camera.is_visible(at(400.0, 610.0), 0.0));
+        assert!(camera.is_visible(at(-10.0, 300.0), 20.0));
+        assert!(camera.is_visible(at(400.0, 610.0), 20.0));
+        assert!(!camera.is_visible(at(830.0, 300.0), 20.0));
+    }
+}
@@ -1,2 +1,4 @@
+pub mod camera;
 pub mod sdl;
 pub mod skia;
+pub mod ui;
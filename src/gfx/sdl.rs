@@ -1,3 +1,4 @@
+use crate::gfx::camera::Camera;
 use crate::gfx::skia::Skia;
 use sdl2::video::{GLContext, GLProfile, Window};
 use sdl2::{EventPump, VideoSubsystem};
@@ -93,8 +94,8 @@ impl Sdl {
         }
     }
 
-    pub fn show_fps(&self, skia: &mut Skia) {
-        let fps = format!("FPS: {:.0} Zoom: {}, Position: {},{}", self.fps, skia.zoom, skia.target.x, skia.target.y);
+    pub fn show_fps(&self, skia: &mut Skia, camera: &Camera) {
+        let fps = format!("FPS: {:.0} Zoom: {}, Position: {},{}", self.fps, camera.zoom, camera.target.x, camera.target.y);
         let mut paint = Paint::default();
         paint.set_style(PaintStyle::StrokeAndFill);
         paint.set_color(skia_safe::Color::BLACK);
@@ -1,3 +1,4 @@
+use crate::gfx::camera::Camera;
 use crate::gfx::sdl::Sdl;
 use rand::Rng;
 use skia_safe::gpu::direct_contexts::make_gl;
@@ -17,20 +18,12 @@ const NOISE_MIX: f32 = 0.075;
 pub const FONT_SIZE: f32 = 14.0;
 pub const LABEL_SIZE: f32 = 1.0;
 
-pub const MIN_ZOOM: f32 = 0.7;
-pub const MAX_ZOOM: f32 = 100.0;
-
 pub struct Skia {
     context: DirectContext,
     pub surface: Surface,
     pub font_main: Font,
     pub font_label: Font,
     pub font_label_bold: Font,
-    pub zoom: f32,
-    pub zoom_min: f32,
-    pub zoom_max: f32,
-    pub target: Point,
-    pub panning: bool,
     pub noise_shader: RuntimeEffect,
     pub drop_shadow: Option<ImageFilter>,
 }
@@ -85,11 +78,6 @@ impl Skia {
             font_main: Font::from_typeface(font_mgr.new_from_data(MAIN_FONT, None).unwrap(), FONT_SIZE),
             font_label: Font::from_typeface(font_mgr.new_from_data(MAIN_FONT, None).unwrap(), LABEL_SIZE),
             font_label_bold: Font::from_typeface(font_mgr.new_from_data(MAIN_FONT_BOLD, None).unwrap(), LABEL_SIZE),
-            zoom: MIN_ZOOM,
-            zoom_min: MIN_ZOOM,
-            zoom_max: MAX_ZOOM,
-            target: Point::new(400.0, -525.0),
-            panning: false,
             noise_shader,
             drop_shadow,
         };
@@ -152,13 +140,11 @@ impl Skia {
         canvas.scale((gfx.dpi, gfx.dpi));
     }
 
-    pub fn set_zoom_target(&mut self, gfx: &Sdl) {
-        let zoom = self.zoom;
-        let target = self.target;
+    pub fn set_zoom_target(&mut self, camera: &Camera) {
         let canvas = self.get_canvas();
-        canvas.translate(gfx.centre);
-        canvas.scale((zoom, zoom));
-        canvas.translate((-target.x, -target.y));
+        canvas.translate(camera.centre);
+        canvas.scale((camera.zoom, camera.zoom));
+        canvas.translate((-camera.target.x, -camera.target.y));
     }
 
     pub fn _clear_matrix(&mut self) {
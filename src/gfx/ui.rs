@@ -0,0 +1,384 @@
+use crate::app_state::AppState;
+use crate::events::{Action, Event, EventBus};
+use crate::game::diplomacy::Relation;
+use crate::game::fog::Vision;
+use crate::game::messages::MessageLog;
+use crate::game::player::PlayerId;
+use crate::game::setup::GameSetup;
+use crate::game::victory::standings;
+use crate::gfx::sdl::Sdl;
+use crate::gfx::skia::Skia;
+use skia_safe::paint::Style;
+use skia_safe::utils::text_utils::Align;
+use skia_safe::{Color, Contains, Paint, Point, Rect};
+
+const BUTTON_WIDTH: f32 = 300.0;
+const BUTTON_HEIGHT: f32 = 36.0;
+const MARGIN: f32 = 10.0;
+const LINE_HEIGHT: f32 = 20.0;
+const MESSAGE_LINES: usize = 5;
+const PANEL_WIDTH: f32 = 260.0;
+const LOG_LINES: usize = 20;
+const SMALL_BUTTON_WIDTH: f32 = 90.0;
+
+// Screen rectangle of the "End Turn" button, bottom right
+pub fn end_turn_button(sdl: &Sdl) -> Rect {
+    Rect::from_xywh(sdl.width as f32 - BUTTON_WIDTH - MARGIN, sdl.height as f32 - BUTTON_HEIGHT - MARGIN, BUTTON_WIDTH, BUTTON_HEIGHT)
+}
+
+pub fn hit(rect: &Rect, x: i32, y: i32) -> bool {
+    rect.contains(Point::new(x as f32, y as f32))
+}
+
+// Clicks on the buttons become what they stand for, the rest fall through to the map
+pub fn handle_click(event: &Event, app_state: &AppState, end_turn_rect: &Rect, diplomacy_rects: &[(Rect, DiplomacyAction)], bus: &mut EventBus) {
+    let (x, y, world) = match *event {
+        Event::Clicked {
+            x,
+            y,
+            world,
+        } => (x, y, world),
+        _ => return,
+    };
+    let diplomacy = diplomacy_rects.iter().find(|(rect, _)| hit(rect, x, y)).map(|&(_, action)| action);
+    if app_state.handover.is_some() {
+        bus.push(Event::Action(Action::DismissHandover));
+    } else if let Some(action) = diplomacy {
+        bus.push(Event::Diplomacy(action));
+    } else if hit(end_turn_rect, x, y) {
+        bus.push(Event::Action(Action::EndTurn));
+    } else {
+        bus.push(Event::MapClicked {
+            world,
+        });
+    }
+}
+
+pub fn draw_button(skia: &mut Skia, rect: Rect, label: &str) {
+    let mut paint = Paint::default();
+    paint.set_anti_alias(true);
+    paint.set_style(Style::Fill);
+    paint.set_color(Color::from_argb(220, 40, 40, 40));
+
+    let mut paint_text = Paint::default();
+    paint_text.set_anti_alias(true);
+    paint_text.set_style(Style::Fill);
+    paint_text.set_color(Color::WHITE);
+
+    let font = skia.font_main.clone();
+    let canvas = skia.get_canvas();
+    canvas.draw_round_rect(rect, 6.0, 6.0, &paint);
+    let baseline = rect.center_y() + font.size() / 3.0;
+    canvas.draw_text_align(label, Point::new(rect.center_x(), baseline), &font, &paint_text, Align::Center);
+}
+
+pub fn draw_panel(skia: &mut Skia, rect: Rect, lines: &[String]) {
+    let mut paint = Paint::default();
+    paint.set_anti_alias(true);
+    paint.set_style(Style::Fill);
+    paint.set_color(Color::from_argb(200, 255, 255, 255));
+
+    let mut paint_text = Paint::default();
+    paint_text.set_anti_alias(true);
+    paint_text.set_style(Style::Fill);
+    paint_text.set_color(Color::BLACK);
+
+    let font = skia.font_main.clone();
+    let canvas = skia.get_canvas();
+    canvas.draw_round_rect(rect, 6.0, 6.0, &paint);
+    lines.iter().enumerate().for_each(|(i, line)| {
+        let p = Point::new(rect.left + MARGIN, rect.top + LINE_HEIGHT * (i + 1) as f32);
+        canvas.draw_text_align(line, p, &font, &paint_text, Align::Left);
+    });
+}
+
+// Most recent messages, bottom left
+pub fn show_messages(skia: &mut Skia, sdl: &Sdl, messages: &MessageLog, viewer: Option<PlayerId>) {
+    let lines: Vec<String> = messages.recent(viewer, MESSAGE_LINES).iter().map(|message| format!("{}: {}", message.turn, message.text)).collect();
+    if lines.is_empty() {
+        return;
+    }
+    let height = LINE_HEIGHT * lines.len() as f32 + MARGIN;
+    let rect = Rect::from_xywh(MARGIN, sdl.height as f32 - height - MARGIN, 500.0, height);
+    draw_panel(skia, rect, &lines);
+}
+
+// Selected city, top right
+pub fn show_city_info(skia: &mut Skia, sdl: &Sdl, app_state: &AppState, vision: Option<&Vision>) {
+    let city = match app_state.selected_city {
+        Some(city) => city,
+        None => return,
+    };
+    let name = app_state.location(city).name.clone();
+    let owner = match app_state.owner_of(city) {
+        Some(owner) => &app_state.players[owner],
+        None => return,
+    };
+    let location = app_state.location(city).world();
+    if vision.is_some_and(|vision| vision.player != app_state.owner_of(city).unwrap_or_default() && !vision.has_intel(location)) {
+        let lines = vec![name, format!("Owner: {}", owner.name), String::from("Send a spy to learn more")];
+        let rect = Rect::from_xywh(sdl.width as f32 - PANEL_WIDTH - MARGIN, MARGIN, PANEL_WIDTH, LINE_HEIGHT * lines.len() as f32 + MARGIN);
+        draw_panel(skia, rect, &lines);
+        return;
+    }
+    let economy = &app_state.cities[city].economy;
+    let mut lines = vec![
+        name,
+        format!("Owner: {}", owner.name),
+        format!("Population: {:.0}", economy.population),
+        format!("Growth: {:+.0} / turn", economy.growth),
+        format!("Income: {:.1} gold / turn", economy.income),
+        format!("Production: {:.1} / turn", economy.production),
+        format!("Treasury: {:.0} gold, {:.0} production", owner.treasury, owner.production),
+    ];
+    if economy.sabotaged > 0 {
+        lines.push(format!("Sabotaged for {} more turns", economy.sabotaged));
+    }
+    let height = LINE_HEIGHT * lines.len() as f32 + MARGIN;
+    let rect = Rect::from_xywh(sdl.width as f32 - PANEL_WIDTH - MARGIN, MARGIN, PANEL_WIDTH, height);
+    draw_panel(skia, rect, &lines);
+}
+
+// Most recent battles, centred
+pub fn show_combat_log(skia: &mut Skia, sdl: &Sdl, app_state: &AppState) {
+    let log = &app_state.combat_log;
+    let mut lines = vec![String::from("Combat log")];
+    lines.extend(log[log.len().saturating_sub(LOG_LINES)..].iter().rev().map(|battle| format!("{}: {}", battle.turn, battle.summary(app_state))));
+    let (width, height) = (sdl.width as f32 * 0.6, LINE_HEIGHT * lines.len() as f32 + MARGIN);
+    let rect = Rect::from_xywh((sdl.width as f32 - width) / 2.0, (sdl.height as f32 - height) / 2.0, width, height);
+    draw_panel(skia, rect, &lines);
+}
+
+// A few lines in the middle of the screen
+pub fn show_notice(skia: &mut Skia, sdl: &Sdl, lines: &[String]) {
+    let (width, height) = (PANEL_WIDTH * 1.5, LINE_HEIGHT * lines.len() as f32 + MARGIN);
+    let rect = Rect::from_xywh((sdl.width as f32 - width) / 2.0, (sdl.height as f32 - height) / 2.0, width, height);
+    draw_panel(skia, rect, lines);
+}
+
+// Hot-seat, nothing of the last player's view shows until the next is ready
+pub fn show_handover(skia: &mut Skia, sdl: &Sdl, name: &str) {
+    let mut paint = Paint::default();
+    paint.set_style(Style::Fill);
+    paint.set_color(Color::from_rgb(30, 30, 30));
+    skia.get_canvas().draw_paint(&paint);
+    show_notice(skia, sdl, &[format!("{} to give orders", name), String::from("Click or press Return when ready")]);
+}
+
+// Who won, and how everyone finished
+pub fn show_game_over(skia: &mut Skia, sdl: &Sdl, app_state: &AppState) {
+    let game_over = match &app_state.game_over {
+        Some(game_over) => game_over,
+        None => return,
+    };
+    let mut lines = vec![String::from("Game over"), game_over.summary(app_state), String::new()];
+    let mut standings = standings(app_state);
+    standings.sort_by(|a, b| b.score.total_cmp(&a.score));
+    lines.extend(standings.iter().map(|standing| {
+        format!(
+            "{}: {} cities, {:.0} people, {} units ({} soldiers), score {:.0}",
+            app_state.players[standing.player].name,
+            standing.cities,
+            standing.population,
+            standing.units,
+            standing.strength,
+            standing.score
+        )
+    }));
+    let (width, height) = (sdl.width as f32 * 0.6, LINE_HEIGHT * lines.len() as f32 + MARGIN);
+    let rect = Rect::from_xywh((sdl.width as f32 - width) / 2.0, (sdl.height as f32 - height) / 2.0, width, height);
+    draw_panel(skia, rect, &lines);
+}
+
+// Selected unit, top right in place of the city
+pub fn show_unit_info(skia: &mut Skia, sdl: &Sdl, app_state: &AppState) {
+    let unit = match app_state.selected_unit.and_then(|id| app_state.units.get(id)) {
+        Some(unit) => unit,
+        None => return,
+    };
+    let mut lines = vec![
+        unit.get_name().to_string(),
+        format!("Owner: {}", app_state.players[unit.owner()].name),
+        format!("Movement: {:.0} min / turn", unit.movement_range() / 60.0),
+        format!("Vision: {:.0} km", unit.vision_radius()),
+        format!("Upkeep: {:.1} gold / turn", unit.upkeep()),
+    ];
+    if let Some(label) = unit.label() {
+        lines.insert(1, format!("Strength: {}", label));
+    }
+    if unit.movement().is_moving() {
+        lines.push(String::from("On the move"));
+    }
+    let height = LINE_HEIGHT * lines.len() as f32 + MARGIN;
+    let rect = Rect::from_xywh(sdl.width as f32 - PANEL_WIDTH - MARGIN, MARGIN, PANEL_WIDTH, height);
+    draw_panel(skia, rect, &lines);
+}
+
+#[derive(Clone, Copy)]
+pub enum SetupAction {
+    ToggleHuman(usize),
+    NextColour(usize),
+    AddPlayer,
+    RemovePlayer,
+    Seed,
+    Victory,
+    Start,
+}
+
+// New game screen, a row for each player then the game options, centred
+pub fn setup_buttons(sdl: &Sdl, setup: &GameSetup) -> Vec<(Rect, SetupAction)> {
+    let width = BUTTON_WIDTH + MARGIN + BUTTON_HEIGHT;
+    let rows = setup.players.len() + 4;
+    let left = (sdl.width as f32 - width) / 2.0;
+    let mut top = (sdl.height as f32 - rows as f32 * (BUTTON_HEIGHT + MARGIN)) / 2.0;
+    let mut buttons = Vec::new();
+    let mut row = |buttons: &mut Vec<(Rect, SetupAction)>, actions: &[(f32, f32, SetupAction)]| {
+        actions.iter().for_each(|&(x, w, action)| buttons.push((Rect::from_xywh(left + x, top, w, BUTTON_HEIGHT), action)));
+        top += BUTTON_HEIGHT + MARGIN;
+    };
+    for i in 0..setup.players.len() {
+        row(&mut buttons, &[(0.0, BUTTON_WIDTH, SetupAction::ToggleHuman(i)), (BUTTON_WIDTH + MARGIN, BUTTON_HEIGHT, SetupAction::NextColour(i))]);
+    }
+    let half = (width - MARGIN) / 2.0;
+    row(&mut buttons, &[(0.0, half, SetupAction::AddPlayer), (half + MARGIN, half, SetupAction::RemovePlayer)]);
+    row(&mut buttons, &[(0.0, width, SetupAction::Seed)]);
+    row(&mut buttons, &[(0.0, width, SetupAction::Victory)]);
+    row(&mut buttons, &[(0.0, width, SetupAction::Start)]);
+    buttons
+}
+
+pub fn show_setup(skia: &mut Skia, sdl: &Sdl, setup: &GameSetup) {
+    let buttons = setup_buttons(sdl, setup);
+    if let (Some((first, _)), Some((last, _))) = (buttons.first(), buttons.last()) {
+        let rect = Rect::new(first.left - MARGIN, first.top - LINE_HEIGHT - MARGIN * 2.0, last.right + MARGIN, last.bottom + MARGIN);
+        draw_panel(skia, rect, &[String::from("New game")]);
+    }
+
+    let mut paint_swatch = Paint::default();
+    paint_swatch.set_anti_alias(true);
+    paint_swatch.set_style(Style::Fill);
+
+    for (rect, action) in buttons {
+        match action {
+            SetupAction::ToggleHuman(i) => {
+                let player = &setup.players[i];
+                let control = if player.human {
+                    "Human"
+                } else {
+                    "Computer"
+                };
+                draw_button(skia, rect, &format!("{}: {}", player.name, control));
+            }
+            SetupAction::NextColour(i) => {
+                paint_swatch.set_color(setup.players[i].colour());
+                skia.get_canvas().draw_round_rect(rect, 6.0, 6.0, &paint_swatch);
+            }
+            SetupAction::AddPlayer => draw_button(skia, rect, "Add player"),
+            SetupAction::RemovePlayer => draw_button(skia, rect, "Remove player"),
+            SetupAction::Seed => {
+                let seed = match setup.seed {
+                    Some(seed) => format!("Seed: {}", seed),
+                    None => String::from("Seed: random, type to choose"),
+                };
+                draw_button(skia, rect, &seed);
+            }
+            SetupAction::Victory => draw_button(skia, rect, &format!("Win by: {}", setup.victory.describe())),
+            SetupAction::Start => draw_button(skia, rect, "Start"),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum DiplomacyAction {
+    DeclareWar(PlayerId),
+    ProposePeace(PlayerId),
+    ProposeAlliance(PlayerId),
+    // The selected city, for gold
+    SellCity(PlayerId),
+    SendGold(PlayerId),
+    Accept(usize),
+    Reject(usize),
+}
+
+// Diplomacy screen for the player giving orders, a row for each rival then one for each proposal to answer
+pub fn diplomacy_buttons(sdl: &Sdl, app_state: &AppState, player: PlayerId) -> Vec<(Rect, DiplomacyAction)> {
+    let rivals: Vec<PlayerId> = (1..app_state.players.len()).filter(|&other| other != player && app_state.players[other].takes_turns()).collect();
+    let proposals: Vec<usize> = app_state.diplomacy.proposals_to(player).map(|proposal| proposal.id).collect();
+    let step = SMALL_BUTTON_WIDTH + MARGIN;
+    let width = BUTTON_WIDTH + step * 5.0;
+    let rows = rivals.len() + proposals.len();
+    let left = (sdl.width as f32 - width) / 2.0 + BUTTON_WIDTH;
+    let mut top = (sdl.height as f32 - rows as f32 * (BUTTON_HEIGHT + MARGIN)) / 2.0;
+    let mut buttons = Vec::new();
+    let mut row = |buttons: &mut Vec<(Rect, DiplomacyAction)>, actions: &[DiplomacyAction]| {
+        let first = step * (5 - actions.len()) as f32;
+        actions
+            .iter()
+            .enumerate()
+            .for_each(|(i, &action)| buttons.push((Rect::from_xywh(left + first + step * i as f32, top, SMALL_BUTTON_WIDTH, BUTTON_HEIGHT), action)));
+        top += BUTTON_HEIGHT + MARGIN;
+    };
+    for other in rivals {
+        row(
+            &mut buttons,
+            &[
+                DiplomacyAction::DeclareWar(other),
+                DiplomacyAction::ProposePeace(other),
+                DiplomacyAction::ProposeAlliance(other),
+                DiplomacyAction::SellCity(other),
+                DiplomacyAction::SendGold(other),
+            ],
+        );
+    }
+    for id in proposals {
+        row(&mut buttons, &[DiplomacyAction::Accept(id), DiplomacyAction::Reject(id)]);
+    }
+    buttons
+}
+
+pub fn show_diplomacy(skia: &mut Skia, sdl: &Sdl, app_state: &AppState, player: PlayerId) {
+    let buttons = diplomacy_buttons(sdl, app_state, player);
+    let (first, last) = match (buttons.first(), buttons.last()) {
+        (Some((first, _)), Some((last, _))) => (*first, *last),
+        _ => return,
+    };
+    let left = last.right - BUTTON_WIDTH - (SMALL_BUTTON_WIDTH + MARGIN) * 5.0 + MARGIN;
+    let rect = Rect::new(left - MARGIN, first.top - LINE_HEIGHT - MARGIN * 2.0, last.right + MARGIN, last.bottom + MARGIN);
+    draw_panel(skia, rect, &[format!("Diplomacy for {}", app_state.players[player].name)]);
+
+    let mut paint_text = Paint::default();
+    paint_text.set_anti_alias(true);
+    paint_text.set_style(Style::Fill);
+    paint_text.set_color(Color::BLACK);
+    let font = skia.font_main.clone();
+
+    // Each row is labelled on the left, by its first button
+    let label = |skia: &mut Skia, rect: &Rect, text: String| {
+        let baseline = rect.center_y() + font.size() / 3.0;
+        skia.get_canvas().draw_text_align(text, Point::new(left, baseline), &font, &paint_text, Align::Left);
+    };
+    for (rect, action) in buttons {
+        match action {
+            DiplomacyAction::DeclareWar(other) => {
+                let relation = match app_state.diplomacy.relation(player, other) {
+                    Relation::War => "at war",
+                    Relation::Peace => "at peace",
+                    Relation::Alliance => "allied",
+                };
+                label(skia, &rect, format!("{}: {}", app_state.players[other].name, relation));
+                draw_button(skia, rect, "War");
+            }
+            DiplomacyAction::ProposePeace(_) => draw_button(skia, rect, "Peace"),
+            DiplomacyAction::ProposeAlliance(_) => draw_button(skia, rect, "Ally"),
+            DiplomacyAction::SellCity(_) => draw_button(skia, rect, "Sell city"),
+            DiplomacyAction::SendGold(_) => draw_button(skia, rect, "Send gold"),
+            DiplomacyAction::Accept(id) => {
+                if let Some(proposal) = app_state.diplomacy.proposals.iter().find(|proposal| proposal.id == id) {
+                    label(skia, &rect, proposal.describe(app_state));
+                }
+                draw_button(skia, rect, "Accept");
+            }
+            DiplomacyAction::Reject(_) => draw_button(skia, rect, "Reject"),
+        }
+    }
+}
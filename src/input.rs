@@ -1,45 +1,128 @@
-use crate::gfx::skia::Skia;
-use sdl2::mouse::{MouseButton, MouseWheelDirection};
-use skia_safe::Point;
+use crate::app_state::Mode;
+use crate::events::{Action, Event, EventBus};
+use crate::geo::crs::Screen;
+use crate::gfx::camera::Camera;
+use sdl2::event::Event as SdlEvent;
+use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
 
 const THRESHOLD: i32 = 64;
 
-pub fn handle_mouse_wheel(skia: &mut Skia, _direction: MouseWheelDirection, precise_y: f32) {
-    let delta = precise_y * 0.05;
-    skia.zoom += delta;
-    skia.zoom = skia.zoom.clamp(skia.zoom_min, skia.zoom_max);
+/// Turns SDL input into events, it only reads the camera to place the cursor on the map.
+#[derive(Default)]
+pub struct Input {
+    // Right button held
+    panning: bool,
 }
 
-pub fn handle_mouse_motion(skia: &mut Skia, centre: Point, x: i32, y: i32, x_rel: i32, y_rel: i32) {
-    let mut mp = Point::new(x as f32, y as f32);
-    mp.x -= centre.x as f32;
-    mp.y -= centre.y as f32;
-    mp.x /= skia.zoom;
-    mp.y /= skia.zoom;
-    mp.x += skia.target.x;
-    mp.y += skia.target.y;
-    if skia.panning {
-        // Calculate mouse movement delta
-        if x_rel.abs() < THRESHOLD && y_rel.abs() < THRESHOLD {
-            // Update camera target based on mouse movement
-            skia.target.x -= x_rel as f32 / skia.zoom;
-            skia.target.y -= y_rel as f32 / skia.zoom;
-        }
-    } else {
-        //terrain.hover(mp);
-    }
-}
+impl Input {
+    pub fn translate(&mut self, event: SdlEvent, camera: &Camera, bus: &mut EventBus) {
+        let world = |x: i32, y: i32| camera.to_world(Screen::new(x as f32, y as f32));
+        match event {
+            SdlEvent::Quit {
+                ..
+            } => bus.push(Event::Quit),
 
-pub fn handle_mouse_button_down(skia: &mut Skia, button: MouseButton) {
-    if button == MouseButton::Right {
-        skia.panning = true;
-    } else if button == MouseButton::Left {
-        //terrain.click(app_state.hover);
-    }
-}
+            SdlEvent::TextInput {
+                text,
+                ..
+            } => {
+                let action = match text.to_uppercase().as_str() {
+                    "Z" => Action::ZoomToSelected,
+                    "X" => {
+                        bus.push(Event::CameraScaled {
+                            factor: 0.5,
+                        });
+                        return;
+                    }
+                    "C" => {
+                        bus.push(Event::CameraScaled {
+                            factor: 2.0,
+                        });
+                        return;
+                    }
+                    "M" => Action::ToggleMode(Mode::Measure),
+                    "R" => Action::ToggleMode(Mode::Route),
+                    "I" => Action::ToggleMode(Mode::Isochrone),
+                    "E" => Action::EndTurn,
+                    #[cfg(debug_assertions)]
+                    "T" => Action::TakeCity,
+                    "A" => Action::RaiseArmy,
+                    "Y" => Action::RecruitSpy,
+                    "B" => Action::Sabotage,
+                    "L" => Action::ToggleCombatLog,
+                    "D" => Action::ToggleDiplomacy,
+                    "U" => Action::ToggleSupply,
+                    "P" => Action::ToggleMeasureClosed,
+                    "S" => Action::ToggleMeasureSnap,
+                    _ => return,
+                };
+                bus.push(Event::Action(action));
+            }
+
+            SdlEvent::KeyDown {
+                keycode: Some(keycode),
+                ..
+            } => {
+                let action = match keycode {
+                    Keycode::RETURN => Action::EndTurn,
+                    Keycode::F5 => Action::Quicksave,
+                    Keycode::F6 => Action::SaveReplay,
+                    Keycode::F9 => Action::Quickload,
+                    Keycode::BACKSPACE => Action::Undo,
+                    Keycode::ESCAPE => Action::Clear,
+                    _ => return,
+                };
+                bus.push(Event::Action(action));
+            }
 
-pub fn handle_mouse_button_up(skia: &mut Skia, button: MouseButton) {
-    if button == MouseButton::Right {
-        skia.panning = false;
+            SdlEvent::MouseWheel {
+                precise_y,
+                ..
+            } => bus.push(Event::CameraZoomed {
+                delta: precise_y * 0.05,
+            }),
+
+            SdlEvent::MouseButtonDown {
+                mouse_btn,
+                x,
+                y,
+                ..
+            } => match mouse_btn {
+                MouseButton::Right => self.panning = true,
+                MouseButton::Left => bus.push(Event::Clicked {
+                    x,
+                    y,
+                    world: world(x, y),
+                }),
+                _ => {}
+            },
+
+            SdlEvent::MouseButtonUp {
+                mouse_btn: MouseButton::Right,
+                ..
+            } => self.panning = false,
+
+            SdlEvent::MouseMotion {
+                x,
+                y,
+                xrel,
+                yrel,
+                ..
+            } => {
+                bus.push(Event::CursorMoved {
+                    world: world(x, y),
+                });
+                // Big jumps are the pointer leaving and re-entering the window, not a drag
+                if self.panning && xrel.abs() < THRESHOLD && yrel.abs() < THRESHOLD {
+                    bus.push(Event::CameraPanned {
+                        dx: xrel as f32,
+                        dy: yrel as f32,
+                    });
+                }
+            }
+
+            _ => {}
+        }
     }
 }
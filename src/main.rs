@@ -1,110 +1,371 @@
-use crate::app_state::AppState;
-use crate::game::player::{Player, PlayerType};
+use crate::app_state::{AppState, Mode};
+use crate::game::ai::{run_headless, take_ai_turn};
+use crate::game::player::PlayerId;
+use crate::game::fog::{draw_fog, Vision};
+use crate::game::replay::{load_recording, save_recording, verify_replay, REPLAY_FILE};
+use crate::game::scenario::load_scenario;
+use crate::game::setup::{new_game, GameSetup};
+use crate::game::supply::draw_supply;
+use crate::game::units::draw_units;
 use crate::geo::cities::draw_all_cities;
+use crate::geo::data::GeoWithPathAndCities;
 use crate::geo::dem::draw_dem;
+use crate::geo::isochrone::{draw_isochrone, parse_bands, show_isochrone};
 use crate::geo::load::{create_geo, load};
+use crate::geo::measure::{draw_measure, show_measurement};
+use crate::geo::route::{draw_route, show_route};
+use crate::geo::territory::draw_territories;
 use crate::geo::ways::draw_ways;
+use crate::gfx::camera::Camera;
 use crate::gfx::sdl::Sdl;
 use crate::gfx::skia::Skia;
-use crate::input::{handle_mouse_button_down, handle_mouse_button_up, handle_mouse_motion, handle_mouse_wheel};
+use crate::gfx::ui::{
+    diplomacy_buttons, draw_button, end_turn_button, handle_click, hit, setup_buttons, show_city_info, show_combat_log, show_diplomacy, show_game_over, show_handover,
+    show_messages, show_notice, show_setup, show_unit_info, SetupAction,
+};
+use crate::net::protocol::DEFAULT_PORT;
+use crate::net::session::Session;
+use crate::events::{Action, Event as BusEvent, EventBus};
+use crate::input::Input;
 use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
+use rand::Rng;
 use std::process::exit;
 use crate::geo::boundary::draw_boundaries;
 // https://osdatahub.os.uk/downloads/open/OpenRoads
 
 mod app_state;
+mod events;
 mod game;
 mod geo;
 mod gfx;
 mod input;
+mod net;
+
+fn start_game(setup: &GameSetup, geo_and_cities: &GeoWithPathAndCities) -> AppState {
+    match new_game(setup, geo_and_cities) {
+        Ok(app_state) => app_state,
+        Err(e) => {
+            println!("Failed to start the game: {}", e);
+            exit(1);
+        }
+    }
+}
+
+// New game screen over the map, until Start is pressed
+fn choose_setup(sdl: &mut Sdl, skia: &mut Skia, camera: &Camera, geo_and_cities: &GeoWithPathAndCities, mut setup: GameSetup) -> GameSetup {
+    loop {
+        sdl.frame_start();
+        skia.set_matrix(sdl);
+        skia.set_zoom_target(camera);
+        draw_dem(skia, &geo_and_cities.dem);
+        draw_boundaries(skia, &geo_and_cities.boundaries);
+
+        let buttons = setup_buttons(sdl, &setup);
+        for event in sdl.event_loop.poll_iter() {
+            match event {
+                Event::Quit {
+                    ..
+                } => exit(0),
+
+                Event::MouseButtonDown {
+                    mouse_btn: MouseButton::Left,
+                    x,
+                    y,
+                    ..
+                } => match buttons.iter().find(|(rect, _)| hit(rect, x, y)).map(|(_, action)| *action) {
+                    Some(SetupAction::ToggleHuman(i)) => setup.toggle_human(i),
+                    Some(SetupAction::NextColour(i)) => setup.next_colour(i),
+                    Some(SetupAction::AddPlayer) => setup.add_player(),
+                    Some(SetupAction::RemovePlayer) => setup.remove_player(),
+                    Some(SetupAction::Seed) => {
+                        setup.seed = match setup.seed {
+                            Some(_) => None,
+                            None => Some(rand::rng().random_range(0..100_000)),
+                        }
+                    }
+                    Some(SetupAction::Victory) => setup.victory = setup.victory.next_preset(),
+                    Some(SetupAction::Start) => return setup,
+                    None => {}
+                },
+
+                // Typing digits sets the seed, backspace takes one off
+                Event::TextInput {
+                    text,
+                    ..
+                } => text.chars().filter_map(|c| c.to_digit(10)).for_each(|digit| setup.type_seed_digit(digit)),
+                Event::KeyDown {
+                    keycode: Some(Keycode::BACKSPACE),
+                    ..
+                } => setup.erase_seed_digit(),
+
+                _ => {}
+            }
+        }
+
+        skia.set_matrix(sdl);
+        show_setup(skia, sdl, &setup);
+        unsafe {
+            skia.flush();
+        }
+        sdl.frame_end();
+    }
+}
+
+// Host's lobby, until every remote seat has been taken
+fn wait_for_players(sdl: &mut Sdl, skia: &mut Skia, camera: &Camera, geo_and_cities: &GeoWithPathAndCities, session: &mut Session, port: u16) {
+    while !session.ready() {
+        sdl.frame_start();
+        skia.set_matrix(sdl);
+        skia.set_zoom_target(camera);
+        draw_dem(skia, &geo_and_cities.dem);
+        draw_boundaries(skia, &geo_and_cities.boundaries);
+
+        for event in sdl.event_loop.poll_iter() {
+            if let Event::Quit {
+                ..
+            } = event
+            {
+                exit(0)
+            }
+        }
+        if let Err(e) = session.accept() {
+            println!("Failed to accept a player: {}", e);
+            exit(1);
+        }
+
+        skia.set_matrix(sdl);
+        let lines = [format!("Hosting on port {}", port), format!("Waiting for {} more player(s) to join", session.open_seats.len())];
+        show_notice(skia, sdl, &lines);
+        unsafe {
+            skia.flush();
+        }
+        sdl.frame_end();
+    }
+}
 
 fn main() {
-    let mut sdl = Sdl::new();
-    let mut skia = Skia::new(&sdl);
+    // --headless <turns> plays computer against computer without a window
+    let args: Vec<String> = std::env::args().collect();
+    let headless = args.iter().position(|arg| arg == "--headless").map(|i| args.get(i + 1).and_then(|turns| turns.parse().ok()).unwrap_or(100));
 
     // Create and load geo data
     //create_geo();
     let geo_and_cities = load(5.0).expect("Failed to load geojson");
 
-    // App state
-    let mut app_state = AppState {
-        players: vec![Player::new(PlayerType::NotAssigned), Player::new(PlayerType::Player)],
-        selected_city: None,
+    // --replay [file] plays a recorded game again and checks it turns out the same
+    if let Some(i) = args.iter().position(|arg| arg == "--replay") {
+        let path = args.get(i + 1).map_or(REPLAY_FILE, |path| path.as_str());
+        let result = load_recording(path).and_then(|recording| verify_replay(&recording, &geo_and_cities));
+        match result {
+            Ok(turns) => println!("Replay of {} matches for all {} turns", path, turns),
+            Err(e) => {
+                println!("Replay of {} failed: {}", path, e);
+                exit(1);
+            }
+        }
+        return;
+    }
+
+    // Players and start cities, from --scenario <file> or else --players and --seed
+    let scenario = args.iter().position(|arg| arg == "--scenario").and_then(|i| args.get(i + 1)).map(|path| match load_scenario(path, &geo_and_cities) {
+        Ok(scenario) => scenario,
+        Err(e) => {
+            println!("Failed to load scenario {}: {}", path, e);
+            exit(1);
+        }
+    });
+    let mut setup = match &scenario {
+        Some(scenario) => scenario.setup(),
+        None => GameSetup::from_args(&args),
+    };
+
+    // --script <file> runs a Rhai script's hooks alongside the game, it's compiled as the game starts
+    if let Some(path) = args.iter().position(|arg| arg == "--script").and_then(|i| args.get(i + 1)) {
+        match std::fs::read_to_string(path) {
+            Ok(source) => setup.script = Some(source),
+            Err(e) => {
+                println!("Failed to load script {}: {}", path, e);
+                exit(1);
+            }
+        }
+    }
+
+    if let Some(turns) = headless {
+        let mut setup = setup;
+        setup.players.iter_mut().for_each(|player| player.human = false);
+        let mut app_state = start_game(&setup, &geo_and_cities);
+        run_headless(&mut app_state, &geo_and_cities, turns);
+        if let Err(e) = save_recording(&app_state.recording, REPLAY_FILE) {
+            println!("Failed to save replay: {}", e);
+        }
+        return;
+    }
+
+    // --join <address:port> plays in someone else's game, which decides the setup
+    let mut session = None;
+    let mut seat = None;
+    if let Some(address) = args.iter().position(|arg| arg == "--join").and_then(|i| args.get(i + 1)) {
+        match Session::join(address) {
+            Ok((joined, joined_seat)) => {
+                session = Some(joined);
+                seat = Some(joined_seat);
+            }
+            Err(e) => {
+                println!("Failed to join {}: {}", address, e);
+                exit(1);
+            }
+        }
+    }
+
+    let mut sdl = Sdl::new();
+    let mut skia = Skia::new(&sdl);
+    let mut camera = Camera::new(sdl.centre);
+    let setup = match &session {
+        Some(session) => session.setup().clone(),
+        None if scenario.is_some() => setup,
+        None => choose_setup(&mut sdl, &mut skia, &camera, &geo_and_cities, setup),
     };
 
-    // Create player(s)
-    app_state.players[0].assign_all(&geo_and_cities);
-    //   let city = app_state.players[0].cities.remove(0);
-    //   app_state.selected_city = Some(city.clone());
-    //    app_state.players[1].change_ownership(city);
-    //    app_state.zoom_to_selected(&mut skia);
+    // --host [port] hands every human seat after the first to players who join
+    let host_port = args.iter().position(|arg| arg == "--host").map(|i| args.get(i + 1).and_then(|port| port.parse().ok()).unwrap_or(DEFAULT_PORT));
+    let mut setup = setup;
+    if let Some(port) = host_port {
+        setup.seed.get_or_insert_with(|| rand::rng().random());
+        let seats: Vec<PlayerId> = setup.players.iter().enumerate().filter(|(_, player)| player.human).skip(1).map(|(i, _)| i + 1).collect();
+        let mut hosted = match Session::host(port, &setup, seats) {
+            Ok(hosted) => hosted,
+            Err(e) => {
+                println!("Failed to host on port {}: {}", port, e);
+                exit(1);
+            }
+        };
+        wait_for_players(&mut sdl, &mut skia, &camera, &geo_and_cities, &mut hosted, port);
+        session = Some(hosted);
+    }
+
+    let mut app_state = start_game(&setup, &geo_and_cities);
+    if let Some(session) = &mut session {
+        session.start(&mut app_state, seat);
+    }
+
+    // --bands <minutes,...> for the drive time isochrones
+    if let Some(text) = args.iter().position(|arg| arg == "--bands").and_then(|i| args.get(i + 1)) {
+        match parse_bands(text) {
+            Some(bands) => app_state.isochrone_bands = bands,
+            None => println!("Ignoring --bands {}, expected minutes such as 10,20,40", text),
+        }
+    }
+    let mut input = Input::default();
+    let mut bus = EventBus::default();
+    bus.push(BusEvent::CameraMoved {
+        camera,
+    });
+    bus.push(BusEvent::Action(Action::ZoomToSelected));
+    let mut last_viewer = app_state.giving_orders_here();
+    // The turn and player the computer last gave orders for, so it only does so once
+    let mut ai_played = None;
 
     loop {
+        let due = app_state.turn.current_player.map(|player| (app_state.turn.turn, player));
+        if due != ai_played && app_state.game_over.is_none() && take_ai_turn(&mut app_state, &geo_and_cities) {
+            ai_played = due;
+        }
+        if let Some(session) = &mut session {
+            session.update(&mut app_state, &geo_and_cities);
+        }
+
         // Start of frame
         sdl.frame_start();
         skia.set_matrix(&sdl);
-        skia.set_zoom_target(&sdl);
+        skia.set_zoom_target(&camera);
         draw_dem(&mut skia, &geo_and_cities.dem);
+        draw_territories(&mut skia, &camera, &app_state.territories, &app_state.players, &geo_and_cities.land);
         draw_boundaries(&mut skia, &geo_and_cities.boundaries);
         draw_ways(&mut skia, &geo_and_cities.ways);
-        draw_all_cities(&mut skia, &app_state);
+        draw_all_cities(&mut skia, &camera, &app_state);
+        let vision = app_state.viewer().map(|player| Vision::new(&app_state, player, &geo_and_cities.roads));
+        if let Some(vision) = &vision {
+            draw_fog(&mut skia, vision);
+        }
+        app_state.update_supply_overlay(&geo_and_cities.roads);
+        if let Some(overlay) = &app_state.supply_overlay {
+            draw_supply(&mut skia, &camera, overlay, &geo_and_cities.roads);
+        }
+        draw_units(&mut skia, &camera, &app_state.units, app_state.selected_unit, &app_state.players, vision.as_ref(), &geo_and_cities.roads);
+        match app_state.mode {
+            Mode::Measure => draw_measure(&mut skia, &camera, &app_state.measure, app_state.cursor),
+            Mode::Route => draw_route(&mut skia, &camera, &app_state.route, &geo_and_cities.roads),
+            Mode::Isochrone => {
+                if let Some(isochrone) = &app_state.isochrone {
+                    draw_isochrone(&mut skia, &camera, isochrone);
+                }
+            }
+            Mode::Normal => {}
+        }
 
         // Events
+        let end_turn_rect = end_turn_button(&sdl);
+        let diplomacy_rects = match app_state.giving_orders_here() {
+            Some(player) if app_state.show_diplomacy => diplomacy_buttons(&sdl, &app_state, player),
+            _ => Vec::new(),
+        };
         for event in sdl.event_loop.poll_iter() {
-            match event {
-                Event::Quit {
-                    ..
-                } => exit(0),
-
-                Event::TextInput {
-                    timestamp: _timestamp,
-                    window_id: _window_id,
-                    text,
-                } => match text.to_uppercase().as_str() {
-                    "Z" => app_state.zoom_to_selected(&mut skia),
-                    "X" => app_state.zoom_out(&mut skia),
-                    "C" => app_state.zoom_in(&mut skia),
-                    _ => {}
-                },
-
-                Event::MouseWheel {
-                    direction,
-                    precise_y,
-                    ..
-                } => {
-                    handle_mouse_wheel(&mut skia, direction, precise_y);
-                }
-
-                Event::MouseButtonDown {
-                    mouse_btn,
-                    ..
-                } => {
-                    handle_mouse_button_down(&mut skia, mouse_btn);
-                }
-                Event::MouseButtonUp {
-                    mouse_btn,
-                    ..
-                } => {
-                    handle_mouse_button_up(&mut skia, mouse_btn);
-                }
-
-                Event::MouseMotion {
-                    x,
-                    y,
-                    xrel: x_rel,
-                    yrel: y_rel,
-                    ..
-                } => {
-                    handle_mouse_motion(&mut skia, sdl.centre, x, y, x_rel, y_rel);
-                }
+            input.translate(event, &camera, &mut bus);
+        }
+        if let Some(session) = &mut session {
+            session.update(&mut app_state, &geo_and_cities);
+        }
 
-                _ => {}
+        // Each part takes what concerns it, whatever it raises in turn goes round again
+        bus.extend(app_state.events.drain(..).map(BusEvent::Game));
+        while let Some(event) = bus.pop() {
+            if let BusEvent::Quit = event {
+                exit(0);
             }
+            camera.handle_event(&event, &mut bus);
+            handle_click(&event, &app_state, &end_turn_rect, &diplomacy_rects, &mut bus);
+            app_state.handle_event(&event, &geo_and_cities, &mut bus);
+            bus.extend(app_state.events.drain(..).map(BusEvent::Game));
+        }
+        app_state.check_handover(last_viewer);
+        if let Some(player) = app_state.giving_orders_here() {
+            last_viewer = Some(player);
         }
 
         // Finish up
         skia.set_matrix(&sdl);
-        sdl.show_fps(&mut skia);
+        sdl.show_fps(&mut skia, &camera);
+        app_state.show_cursor_position(&mut skia);
+        match app_state.mode {
+            Mode::Measure => show_measurement(&mut skia, &app_state.measure),
+            Mode::Route => show_route(&mut skia, &app_state.route),
+            Mode::Isochrone => show_isochrone(&mut skia, &app_state.isochrone),
+            Mode::Normal => {}
+        }
+        show_messages(&mut skia, &sdl, &app_state.messages, app_state.viewer());
+        if app_state.selected_unit.is_some() {
+            show_unit_info(&mut skia, &sdl, &app_state);
+        } else {
+            show_city_info(&mut skia, &sdl, &app_state, vision.as_ref());
+        }
+        if app_state.show_combat_log {
+            show_combat_log(&mut skia, &sdl, &app_state);
+        }
+        let status = match session.as_ref().and_then(|session| session.desync.as_ref()) {
+            Some(error) => format!("Stopped: {}, F5 saves", error),
+            None if app_state.game_over.is_some() => String::from("Game over"),
+            None => format!("End Turn  ({})", app_state.turn.status(&app_state.players)),
+        };
+        draw_button(&mut skia, end_turn_rect, &status);
+        if let Some(player) = app_state.giving_orders_here().filter(|_| app_state.show_diplomacy) {
+            show_diplomacy(&mut skia, &sdl, &app_state, player);
+        }
+        show_game_over(&mut skia, &sdl, &app_state);
+        if let Some(player) = app_state.handover {
+            show_handover(&mut skia, &sdl, &app_state.players[player].name);
+        }
         unsafe {
             skia.flush();
         }
@@ -0,0 +1,100 @@
+use crate::game::command::Command;
+use crate::game::player::PlayerId;
+use crate::game::setup::GameSetup;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::io::{BufReader, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::thread;
+
+pub const DEFAULT_PORT: u16 = 7878;
+
+// Anything bigger is a broken stream rather than a real message
+const MAX_MESSAGE: usize = 16 * 1024 * 1024;
+
+#[derive(Serialize, Deserialize)]
+pub enum Message {
+    // Host to a new client: which player it is and how to set the game up, seed included
+    Welcome {
+        seat: PlayerId,
+        // Boxed as it can carry a whole scenario
+        setup: Box<GameSetup>,
+    },
+    // One player's orders for a turn, ending with EndTurn. hash is the state before they were given,
+    // so every instance can check it's in step before carrying them out.
+    Orders {
+        player: PlayerId,
+        turn: u32,
+        hash: u64,
+        commands: Vec<Command>,
+    },
+}
+
+/// A TCP link to another instance. Messages are CBOR, each preceded by its length as a
+/// big endian u32. A thread reads them so the game loop never waits on the network.
+pub struct Connection {
+    stream: TcpStream,
+    incoming: Receiver<Result<Message, String>>,
+    pub seat: PlayerId,
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream, seat: PlayerId) -> Result<Connection, Box<dyn Error>> {
+        stream.set_nodelay(true)?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let (sender, incoming) = channel();
+        thread::spawn(move || loop {
+            let message = read_message(&mut reader);
+            let failed = message.is_err();
+            if sender.send(message.map_err(|e| e.to_string())).is_err() || failed {
+                break;
+            }
+        });
+        Ok(Connection {
+            stream,
+            incoming,
+            seat,
+        })
+    }
+
+    pub fn send(&mut self, message: &Message) -> Result<(), Box<dyn Error>> {
+        let bytes = serde_cbor::to_vec(message)?;
+        self.stream.write_all(&(bytes.len() as u32).to_be_bytes())?;
+        self.stream.write_all(&bytes)?;
+        Ok(())
+    }
+
+    // The next message if one has arrived
+    pub fn try_receive(&self) -> Result<Option<Message>, String> {
+        match self.incoming.try_recv() {
+            Ok(message) => message.map(Some),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err(String::from("disconnected")),
+        }
+    }
+
+    // Wait for the next message, used before the game starts
+    pub fn receive(&self) -> Result<Message, String> {
+        self.incoming.recv().map_err(|_| String::from("disconnected"))?
+    }
+}
+
+// Wakes the reading thread, and lets the other end know straight away
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+fn read_message(reader: &mut impl Read) -> Result<Message, Box<dyn Error>> {
+    let mut length = [0u8; 4];
+    reader.read_exact(&mut length)?;
+    let length = u32::from_be_bytes(length) as usize;
+    if length > MAX_MESSAGE {
+        return Err(format!("message of {} bytes is too big", length).into());
+    }
+    let mut bytes = vec![0u8; length];
+    reader.read_exact(&mut bytes)?;
+    Ok(serde_cbor::from_slice(&bytes)?)
+}
@@ -0,0 +1,429 @@
+use crate::app_state::AppState;
+use crate::game::ai::Heuristic;
+use crate::game::command::{execute, Command};
+use crate::game::player::{PlayerId, PlayerType};
+use crate::game::replay::{state_hash, Baseline};
+use crate::game::setup::GameSetup;
+use crate::geo::data::GeoWithPathAndCities;
+use crate::net::protocol::{Connection, Message};
+use std::error::Error;
+use std::io::ErrorKind;
+use std::net::{TcpListener, TcpStream};
+
+/// A networked game in lockstep. Every instance runs the whole game; only orders are sent.
+/// Clients talk to the host, which passes each player's orders on to everyone else, and the
+/// state hash sent with them catches any instance that has drifted out of step.
+pub struct Session {
+    pub host: bool,
+    listener: Option<TcpListener>,
+    connections: Vec<Connection>,
+    setup: GameSetup,
+    // Seats still waiting for someone to join, host only
+    pub open_seats: Vec<PlayerId>,
+    // Recorded commands already shared or received
+    sent: usize,
+    // Set once instances disagree, after which no more orders are carried out
+    pub desync: Option<String>,
+}
+
+impl Session {
+    // Listen for players to take the given seats, which are usually the humans after the first
+    pub fn host(port: u16, setup: &GameSetup, seats: Vec<PlayerId>) -> Result<Session, Box<dyn Error>> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        listener.set_nonblocking(true)?;
+        Ok(Session {
+            host: true,
+            listener: Some(listener),
+            connections: Vec::new(),
+            setup: setup.clone(),
+            open_seats: seats,
+            sent: 0,
+            desync: None,
+        })
+    }
+
+    // Connect to a host and wait to be given a seat and the game setup
+    pub fn join(address: &str) -> Result<(Session, PlayerId), Box<dyn Error>> {
+        let connection = Connection::new(TcpStream::connect(address)?, 0)?;
+        let (seat, setup) = match connection.receive()? {
+            Message::Welcome {
+                seat,
+                setup,
+            } => (seat, *setup),
+            _ => return Err("Expected a welcome from the host".into()),
+        };
+        // Seat 0 is the unassigned cities
+        if seat == 0 || seat > setup.players.len() {
+            return Err(format!("Host gave us seat {} of {}", seat, setup.players.len()).into());
+        }
+        let session = Session {
+            host: false,
+            listener: None,
+            connections: vec![connection],
+            setup,
+            open_seats: Vec::new(),
+            sent: 0,
+            desync: None,
+        };
+        Ok((session, seat))
+    }
+
+    pub fn setup(&self) -> &GameSetup {
+        &self.setup
+    }
+
+    pub fn ready(&self) -> bool {
+        self.open_seats.is_empty()
+    }
+
+    // Take in anyone waiting to join while there are seats left
+    pub fn accept(&mut self) -> Result<(), Box<dyn Error>> {
+        let listener = match &self.listener {
+            Some(listener) => listener,
+            None => return Ok(()),
+        };
+        while !self.open_seats.is_empty() {
+            let stream = match listener.accept() {
+                Ok((stream, address)) => {
+                    println!("{} joined", address);
+                    stream
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e.into()),
+            };
+            stream.set_nonblocking(false)?;
+            let seat = self.open_seats.remove(0);
+            let mut connection = Connection::new(stream, seat)?;
+            connection.send(&Message::Welcome {
+                seat,
+                setup: Box::new(self.setup.clone()),
+            })?;
+            self.connections.push(connection);
+        }
+        Ok(())
+    }
+
+    // Everyone else's seats are played elsewhere: the clients' on a host, all but our own on a client
+    pub fn start(&mut self, app_state: &mut AppState, seat: Option<PlayerId>) {
+        app_state.remote_players = match seat {
+            Some(seat) => (0..app_state.players.len()).filter(|&player| player != seat && app_state.players[player].takes_turns()).collect(),
+            None => self.connections.iter().map(|connection| connection.seat).collect(),
+        };
+        app_state.selected_city = app_state.viewer().and_then(|player| app_state.players[player].cities.first().copied());
+        self.sent = app_state.recording.commands.len();
+        app_state.baselines = vec![Baseline::of(app_state)];
+    }
+
+    /// Call after anything that may have given orders: shares finished local turns,
+    /// then carries out any that have arrived.
+    pub fn update(&mut self, app_state: &mut AppState, geo_and_cities: &GeoWithPathAndCities) {
+        if self.desync.is_some() {
+            return;
+        }
+        self.send_local(app_state);
+        self.receive_remote(app_state, geo_and_cities);
+    }
+
+    // Orders are only sent once the player ends their turn, one message for each turn ended since
+    // the last update, each with the player and state that turn started from
+    fn send_local(&mut self, app_state: &mut AppState) {
+        // Nobody left to tell, the computer has taken over everyone else's seats
+        if self.connections.is_empty() {
+            self.sent = app_state.recording.commands.len();
+            app_state.baselines.clear();
+            return;
+        }
+        while let Some(end) = app_state.recording.commands[self.sent..].iter().position(|command| matches!(command, Command::EndTurn { .. })) {
+            let commands = app_state.recording.commands[self.sent..=self.sent + end].to_vec();
+            let baseline = app_state.baselines.iter().find(|baseline| baseline.commands == self.sent).copied();
+            self.sent += end + 1;
+            self.forget_baselines(app_state);
+            let baseline = match baseline {
+                Some(baseline) => baseline,
+                None => {
+                    self.fail(app_state, format!("Lost track of where turn {} began", app_state.turn.turn));
+                    return;
+                }
+            };
+            let message = Message::Orders {
+                player: baseline.player.unwrap_or_default(),
+                turn: baseline.turn,
+                hash: baseline.hash,
+                commands,
+            };
+            self.broadcast(app_state, &message, None);
+        }
+    }
+
+    // Only the turn still being played is needed
+    fn forget_baselines(&self, app_state: &mut AppState) {
+        app_state.baselines.retain(|baseline| baseline.commands >= self.sent);
+    }
+
+    fn receive_remote(&mut self, app_state: &mut AppState, geo_and_cities: &GeoWithPathAndCities) {
+        let mut index = 0;
+        while index < self.connections.len() {
+            let message = match self.connections[index].try_receive() {
+                Ok(Some(message)) => message,
+                Ok(None) => {
+                    index += 1;
+                    continue;
+                }
+                Err(e) => {
+                    self.lose_connection(app_state, index, e);
+                    continue;
+                }
+            };
+            if let Message::Orders {
+                player,
+                turn,
+                hash,
+                commands,
+            } = &message
+            {
+                let seat = self.connections[index].seat;
+                if *player >= app_state.players.len() {
+                    self.fail(app_state, format!("{} sent orders for player {}, who doesn't exist", self.peer_name(app_state, index), player));
+                    return;
+                }
+                if self.host && *player != seat {
+                    self.fail(app_state, format!("{} sent orders for {}", app_state.players[seat].name, app_state.players[*player].name));
+                    return;
+                }
+                if let Err(e) = self.apply(app_state, geo_and_cities, *player, *turn, *hash, commands) {
+                    self.fail(app_state, e);
+                    return;
+                }
+                // The host passes them on to everyone else, anyone lost meanwhile shifts the indices so start again next time
+                if self.host {
+                    let connected = self.connections.len();
+                    self.broadcast(app_state, &message, Some(index));
+                    if self.connections.len() != connected {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    fn apply(
+        &mut self,
+        app_state: &mut AppState,
+        geo_and_cities: &GeoWithPathAndCities,
+        player: PlayerId,
+        turn: u32,
+        hash: u64,
+        commands: &[Command],
+    ) -> Result<(), String> {
+        let name = app_state.players[player].name.clone();
+        if app_state.turn.current_player != Some(player) || app_state.turn.turn != turn {
+            return Err(format!("Orders from {} for turn {} arrived out of turn", name, turn));
+        }
+        if state_hash(app_state) != hash {
+            return Err(format!("Out of step with {} on turn {}", name, turn));
+        }
+        // The debugging cheat is for one machine only
+        #[cfg(debug_assertions)]
+        if commands.iter().any(|command| matches!(command, Command::TakeCity { .. })) {
+            return Err(format!("{} tried to take a city by cheating", name));
+        }
+        for command in commands {
+            if !execute(app_state, geo_and_cities, command.clone()) {
+                return Err(format!("Couldn't carry out {:?} from {}", command, name));
+            }
+        }
+        self.sent = app_state.recording.commands.len();
+        self.forget_baselines(app_state);
+        Ok(())
+    }
+
+    fn broadcast(&mut self, app_state: &mut AppState, message: &Message, except: Option<usize>) {
+        let mut failed = Vec::new();
+        for index in (0..self.connections.len()).filter(|&index| Some(index) != except) {
+            if let Err(e) = self.connections[index].send(message) {
+                failed.push((index, e.to_string()));
+            }
+        }
+        for (index, error) in failed.into_iter().rev() {
+            self.lose_connection(app_state, index, error);
+        }
+    }
+
+    // The computer plays whatever seats were reached through a lost connection, so the game carries on:
+    // a client's own seat on the host, every other seat on a client that loses the host
+    fn lose_connection(&mut self, app_state: &mut AppState, index: usize, error: String) {
+        let name = self.peer_name(app_state, index);
+        let connection = self.connections.remove(index);
+        let seats = if self.host {
+            vec![connection.seat]
+        } else {
+            app_state.remote_players.clone()
+        };
+        app_state.remote_players.retain(|player| !seats.contains(player));
+        for &seat in &seats {
+            app_state.players[seat].player_type = PlayerType::Ai(Box::new(Heuristic));
+        }
+        let names: Vec<&str> = seats.iter().map(|&seat| app_state.players[seat].name.as_str()).collect();
+        let message = format!("{} lost ({}), the computer plays {} from now on", name, error, names.join(", "));
+        app_state.messages.add(app_state.turn.turn, message);
+    }
+
+    // Clients only ever talk to the host
+    fn peer_name(&self, app_state: &AppState, index: usize) -> String {
+        if self.host {
+            app_state.players[self.connections[index].seat].name.clone()
+        } else {
+            String::from("Host")
+        }
+    }
+
+    fn fail(&mut self, app_state: &mut AppState, error: String) {
+        app_state.messages.add(app_state.turn.turn, error.clone());
+        self.desync = Some(error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::ai::take_ai_turn;
+    use crate::game::setup::new_game;
+    use crate::geo::data::test_geo;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    // Two humans, the second joining over loopback
+    fn connect() -> (Session, Session, PlayerId) {
+        let mut setup = GameSetup::default();
+        setup.players.iter_mut().for_each(|player| player.human = true);
+        connect_to(setup, 2)
+    }
+
+    fn connect_to(mut setup: GameSetup, seat: PlayerId) -> (Session, Session, PlayerId) {
+        setup.seed = Some(5);
+        let mut host = Session::host(0, &setup, vec![seat]).unwrap();
+        let port = host.listener.as_ref().unwrap().local_addr().unwrap().port();
+        let joining = thread::spawn(move || Session::join(&format!("127.0.0.1:{}", port)).map_err(|e| e.to_string()));
+        while !host.ready() {
+            host.accept().unwrap();
+            thread::sleep(Duration::from_millis(5));
+        }
+        let (client, seat) = joining.join().unwrap().unwrap();
+        (host, client, seat)
+    }
+
+    // Keep updating until the condition holds, or give up after a few seconds
+    fn wait_for(session: &mut Session, app_state: &mut AppState, geo_and_cities: &GeoWithPathAndCities, done: impl Fn(&AppState) -> bool) {
+        let start = Instant::now();
+        while !done(app_state) {
+            assert!(start.elapsed() < Duration::from_secs(5), "timed out");
+            session.update(app_state, geo_and_cities);
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn turns_are_exchanged_over_loopback() {
+        let geo_and_cities = test_geo();
+        let (mut host, mut client, seat) = connect();
+        assert_eq!(seat, 2);
+        let mut hosted = new_game(host.setup(), &geo_and_cities).unwrap();
+        let mut joined = new_game(client.setup(), &geo_and_cities).unwrap();
+        host.start(&mut hosted, None);
+        client.start(&mut joined, Some(seat));
+        assert_eq!(state_hash(&hosted), state_hash(&joined));
+
+        assert!(execute(
+            &mut hosted,
+            &geo_and_cities,
+            Command::EndTurn {
+                player: 1,
+            }
+        ));
+        host.update(&mut hosted, &geo_and_cities);
+        wait_for(&mut client, &mut joined, &geo_and_cities, |app_state| app_state.turn.current_player == Some(2));
+
+        assert!(execute(
+            &mut joined,
+            &geo_and_cities,
+            Command::EndTurn {
+                player: 2,
+            }
+        ));
+        client.update(&mut joined, &geo_and_cities);
+        wait_for(&mut host, &mut hosted, &geo_and_cities, |app_state| app_state.turn.turn == 2);
+
+        assert!(host.desync.is_none() && client.desync.is_none());
+        assert_eq!(joined.turn.turn, 2);
+        assert_eq!(state_hash(&hosted), state_hash(&joined));
+    }
+
+    // The host's human and then its computer player end their turns before the session hears of either
+    #[test]
+    fn turns_ended_together_are_each_checked() {
+        let geo_and_cities = test_geo();
+        let mut setup = GameSetup::default();
+        setup.add_player();
+        setup.players.iter_mut().enumerate().for_each(|(index, player)| player.human = index != 1);
+        let (mut host, mut client, seat) = connect_to(setup, 3);
+        let mut hosted = new_game(host.setup(), &geo_and_cities).unwrap();
+        let mut joined = new_game(client.setup(), &geo_and_cities).unwrap();
+        host.start(&mut hosted, None);
+        client.start(&mut joined, Some(seat));
+
+        assert!(execute(
+            &mut hosted,
+            &geo_and_cities,
+            Command::EndTurn {
+                player: 1,
+            }
+        ));
+        assert!(take_ai_turn(&mut hosted, &geo_and_cities));
+        assert_eq!(hosted.turn.current_player, Some(seat));
+        host.update(&mut hosted, &geo_and_cities);
+
+        // A message for each turn, each checked against the state it started from as it's carried out
+        for player in [1, 2] {
+            match client.connections[0].receive().unwrap() {
+                Message::Orders {
+                    player: from,
+                    turn,
+                    hash,
+                    commands,
+                } => {
+                    assert_eq!(from, player);
+                    client.apply(&mut joined, &geo_and_cities, from, turn, hash, &commands).unwrap();
+                }
+                _ => panic!("expected orders"),
+            }
+        }
+        assert_eq!(joined.turn.current_player, Some(seat));
+        assert_eq!(state_hash(&hosted), state_hash(&joined));
+
+        assert!(execute(
+            &mut joined,
+            &geo_and_cities,
+            Command::EndTurn {
+                player: seat,
+            }
+        ));
+        client.update(&mut joined, &geo_and_cities);
+        wait_for(&mut host, &mut hosted, &geo_and_cities, |app_state| app_state.turn.turn == 2);
+
+        assert!(host.desync.is_none() && client.desync.is_none());
+        assert_eq!(state_hash(&hosted), state_hash(&joined));
+    }
+
+    #[test]
+    fn the_computer_takes_over_from_a_lost_player() {
+        let geo_and_cities = test_geo();
+        let (mut host, client, seat) = connect();
+        let mut hosted = new_game(host.setup(), &geo_and_cities).unwrap();
+        host.start(&mut hosted, None);
+        drop(client);
+
+        wait_for(&mut host, &mut hosted, &geo_and_cities, |app_state| app_state.remote_players.is_empty());
+        assert!(host.desync.is_none());
+        assert!(matches!(hosted.players[seat].player_type, PlayerType::Ai(_)));
+    }
+}